@@ -3,12 +3,15 @@ use std::process::Command;
 use std::str;
 
 fn main() {
+    println!("cargo:rustc-check-cfg=cfg(backtrace)");
+
     let compiler = match rustc_version() {
         Some(compiler) => compiler,
         None => return,
     };
 
-    if compiler.nightly {
+    // std::backtrace is only available with the standard library.
+    if compiler.nightly && env::var_os("CARGO_FEATURE_STD").is_some() {
         println!("cargo:rustc-cfg=backtrace");
     }
 }
@@ -18,10 +21,7 @@ struct Compiler {
 }
 
 fn rustc_version() -> Option<Compiler> {
-    let rustc = match env::var_os("RUSTC") {
-        Some(rustc) => rustc,
-        None => return None,
-    };
+    let rustc = env::var_os("RUSTC")?;
 
     let output = match Command::new(rustc).arg("--version").output() {
         Ok(output) => output,
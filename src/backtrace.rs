@@ -0,0 +1,230 @@
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::fmt;
+
+/// A single frame of a captured backtrace.
+///
+/// This type is the item of the iterator returned by
+/// [`Error::backtrace_frames`][crate::Error::backtrace_frames].
+///
+/// How much detail is available depends on the debug info present in the
+/// binary. Without debug info the file and line are usually missing, and the
+/// symbol may be `<unknown>`.
+#[derive(Clone, Debug)]
+pub struct BacktraceFrame {
+    symbol: String,
+    file: Option<String>,
+    line: Option<u32>,
+    column: Option<u32>,
+}
+
+impl BacktraceFrame {
+    /// The demangled name of the function executing in this frame.
+    pub fn symbol(&self) -> &str {
+        &self.symbol
+    }
+
+    /// The source file of the code executing in this frame.
+    pub fn file(&self) -> Option<&str> {
+        self.file.as_deref()
+    }
+
+    /// The line number of the code executing in this frame.
+    pub fn line(&self) -> Option<u32> {
+        self.line
+    }
+
+    /// The column number of the code executing in this frame.
+    pub fn column(&self) -> Option<u32> {
+        self.column
+    }
+}
+
+pub(crate) fn frames(backtrace: &Backtrace) -> Vec<BacktraceFrame> {
+    let mut frames = Vec::new();
+    if backtrace.status() != BacktraceStatus::Captured {
+        return frames;
+    }
+
+    // The standard library does not yet expose the contents of a frame, so
+    // they are recovered from the Display representation which looks like:
+    //
+    //    0: crate::module::function
+    //              at ./src/module.rs:10:5
+    for line in backtrace.to_string().lines() {
+        let line = line.trim_start();
+        if let Some(location) = line.strip_prefix("at ") {
+            if let Some(frame) = frames.last_mut() {
+                parse_location(frame, location);
+            }
+            continue;
+        }
+        let symbol = match line.find(": ") {
+            Some(i) if line[..i].bytes().all(|b| b.is_ascii_digit()) => &line[i + 2..],
+            _ => line,
+        };
+        frames.push(BacktraceFrame {
+            symbol: symbol.to_owned(),
+            file: None,
+            line: None,
+            column: None,
+        });
+    }
+    frames
+}
+
+// The frames of a backtrace captured by anyhow starting at the code that
+// created the error. The standard library has no way to capture a backtrace
+// from a given frame onwards, so the frames of the capture itself are still
+// stored and are dropped here instead.
+pub(crate) fn call_site_frames(backtrace: &Backtrace) -> Vec<BacktraceFrame> {
+    let mut frames = frames(backtrace);
+    let skip = frames
+        .iter()
+        .take_while(|frame| is_capture_frame(&frame.symbol))
+        .count();
+    frames.drain(..skip);
+    frames
+}
+
+// Writes the Display representation of a captured backtrace, optionally
+// leaving out the frames of the capture itself and printing no more than
+// `limit` frames. The frame numbers are right-aligned to `indent` columns, which is
+// 4 in the standard library's own rendering.
+pub(crate) fn write_frames(
+    f: &mut fmt::Formatter,
+    backtrace: &Backtrace,
+    limit: usize,
+    trim: bool,
+    indent: usize,
+) -> fmt::Result {
+    let rendered = backtrace.to_string();
+
+    // Each frame is its symbol line followed by any number of location lines.
+    let mut frames: Vec<(&str, Vec<&str>)> = Vec::new();
+    for line in rendered.lines() {
+        match frame_symbol(line) {
+            Some(symbol) => frames.push((symbol, Vec::new())),
+            None => match frames.last_mut() {
+                Some((_, locations)) => locations.push(line),
+                None => writeln!(f, "{}", line)?,
+            },
+        }
+    }
+
+    let skip = if trim {
+        frames
+            .iter()
+            .take_while(|(symbol, _)| is_capture_frame(symbol))
+            .count()
+    } else {
+        0
+    };
+
+    let frames = &frames[skip..];
+    for (i, (symbol, locations)) in frames.iter().take(limit).enumerate() {
+        writeln!(f, "{:indent$}: {}", i, symbol, indent = indent)?;
+        for location in locations {
+            let location = location.trim_start();
+            writeln!(f, "{:indent$}{}", "", location, indent = indent + 9)?;
+        }
+    }
+    if frames.len() > limit {
+        writeln!(
+            f,
+            "{:indent$}... truncated {} more frames",
+            "",
+            frames.len() - limit,
+            indent = indent + 2,
+        )?;
+    }
+    Ok(())
+}
+
+fn frame_symbol(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    let i = line.find(": ")?;
+    if i > 0 && line[..i].bytes().all(|b| b.is_ascii_digit()) {
+        Some(&line[i + 2..])
+    } else {
+        None
+    }
+}
+
+// Frames of the standard library capturing the backtrace and of anyhow
+// constructing the error, which are at the top of every backtrace captured by
+// anyhow. Frames of this crate's own unit tests are kept, as those are where
+// the error was created.
+fn is_capture_frame(symbol: &str) -> bool {
+    if symbol.starts_with("std::backtrace") || symbol.starts_with("std::sys::backtrace") {
+        return true;
+    }
+    let anyhow = symbol.starts_with("anyhow::")
+        || symbol.starts_with("<anyhow::")
+        || symbol.contains(" as anyhow::");
+    anyhow && !symbol.contains("::tests::")
+}
+
+fn parse_location(frame: &mut BacktraceFrame, location: &str) {
+    let mut parts = location.rsplitn(3, ':');
+    let column = parts.next().and_then(|column| column.parse().ok());
+    let line = parts.next().and_then(|line| line.parse().ok());
+    match (parts.next(), line) {
+        (Some(file), Some(line)) => {
+            frame.file = Some(file.to_owned());
+            frame.line = Some(line);
+            frame.column = column;
+        }
+        _ => frame.file = Some(location.to_owned()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+    use std::io;
+
+    struct Rendered<'a>(&'a Backtrace, usize, bool);
+
+    impl<'a> fmt::Display for Rendered<'a> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            let indent = f.width().unwrap_or(4);
+            write_frames(f, self.0, self.1, self.2, indent)
+        }
+    }
+
+    #[test]
+    fn truncate_frames() {
+        let backtrace = Backtrace::force_capture();
+        let total = frames(&backtrace).len();
+        assert!(total > 2);
+
+        let rendered = Rendered(&backtrace, 2, false).to_string();
+        let marker = format!("... truncated {} more frames", total - 2);
+        assert!(rendered.trim_end().ends_with(&marker));
+        assert_eq!(rendered.lines().filter_map(frame_symbol).count(), 2,);
+
+        let rendered = Rendered(&backtrace, total, false).to_string();
+        assert!(!rendered.contains("truncated"));
+        assert_eq!(rendered.lines().filter_map(frame_symbol).count(), total);
+    }
+
+    #[test]
+    fn trim_capture_frames() {
+        let error = Error::new(io::Error::from(io::ErrorKind::NotFound));
+        let backtrace = error.backtrace();
+        if backtrace.status() != BacktraceStatus::Captured {
+            return;
+        }
+
+        let full = Rendered(backtrace, usize::MAX, false).to_string();
+        assert!(full.contains("anyhow::error::Error"));
+
+        let trimmed = Rendered(backtrace, usize::MAX, true).to_string();
+        assert!(!trimmed.contains("anyhow::error::Error::construct"));
+        assert!(!trimmed.contains("anyhow::error::Error"));
+        assert!(!trimmed.contains("std::backtrace"));
+        let first = trimmed.lines().find_map(frame_symbol).unwrap();
+        assert!(first.contains("trim_capture_frames"));
+    }
+}
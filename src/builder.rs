@@ -0,0 +1,185 @@
+use crate::Error;
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::error::Error as StdError;
+use core::fmt::Display;
+
+#[cfg(backtrace)]
+use std::backtrace::Backtrace;
+
+/// Builder for an [`Error`] with several options set at once.
+///
+/// Created by [`Error::builder`]. At least one of [`message`] or [`source`]
+/// must be set before calling [`build`]:
+///
+/// - With only a message, the result is the same as [`Error::msg`].
+/// - With only a source, the result is the same as [`Error::new`].
+/// - With both, the message is attached as context on top of the source, as
+///   if by [`Error::context`], so it is displayed first and the source is its
+///   cause.
+///
+/// Notes and the error code may be combined with either. The message is
+/// stored as a `String`, which is the type to downcast to if no source is
+/// set.
+///
+/// [`message`]: ErrorBuilder::message
+/// [`source`]: ErrorBuilder::source
+/// [`build`]: ErrorBuilder::build
+#[must_use]
+pub struct ErrorBuilder {
+    message: Option<String>,
+    source: Option<Box<dyn FnOnce(bool) -> Error>>,
+    notes: Vec<Box<dyn Display + Send + Sync>>,
+    code: Option<&'static str>,
+    capture_backtrace: bool,
+}
+
+impl ErrorBuilder {
+    pub(crate) fn new() -> Self {
+        ErrorBuilder {
+            message: None,
+            source: None,
+            notes: Vec::new(),
+            code: None,
+            capture_backtrace: true,
+        }
+    }
+
+    /// Set the message displayed for this error.
+    pub fn message<M>(mut self, message: M) -> Self
+    where
+        M: Display,
+    {
+        self.message = Some(message.to_string());
+        self
+    }
+
+    /// Set the underlying error. If a message is also set, this becomes its
+    /// cause.
+    pub fn source<E>(mut self, source: E) -> Self
+    where
+        E: StdError + Send + Sync + 'static,
+    {
+        self.source = Some(Box::new(move |capture_backtrace| {
+            if capture_backtrace {
+                Error::new(source)
+            } else {
+                Error::new_without_backtrace(source)
+            }
+        }));
+        self
+    }
+
+    /// Attach a note, as by [`Error::note`]. May be called more than once.
+    pub fn note<N>(mut self, note: N) -> Self
+    where
+        N: Display + Send + Sync + 'static,
+    {
+        self.notes.push(Box::new(note));
+        self
+    }
+
+    /// Set the error code, as by [`Error::with_code`].
+    pub fn code(mut self, code: &'static str) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    /// Whether to capture a backtrace when the error is built. Defaults to
+    /// `true`, in which case the usual `RUST_BACKTRACE` and
+    /// `RUST_LIB_BACKTRACE` settings still apply.
+    ///
+    /// A source that already provides its own backtrace keeps it either way.
+    pub fn capture_backtrace(mut self, capture: bool) -> Self {
+        self.capture_backtrace = capture;
+        self
+    }
+
+    /// Build the error.
+    ///
+    /// # Panics
+    ///
+    /// Panics if neither a message nor a source was set.
+    pub fn build(self) -> Error {
+        let mut error = match (self.message, self.source) {
+            (Some(message), None) => {
+                if self.capture_backtrace {
+                    Error::new_adhoc_captured(message)
+                } else {
+                    #[cfg(backtrace)]
+                    let backtrace = Some(Backtrace::disabled());
+
+                    #[cfg(not(backtrace))]
+                    let backtrace = None;
+
+                    Error::new_adhoc(message, backtrace)
+                }
+            }
+            (message, Some(source)) => {
+                let error = source(self.capture_backtrace);
+                match message {
+                    Some(message) => error.context(message),
+                    None => error,
+                }
+            }
+            (None, None) => panic!("ErrorBuilder::build called without a message or a source"),
+        };
+
+        for note in self.notes {
+            error = error.note(note);
+        }
+        if let Some(code) = self.code {
+            error = error.with_code(code);
+        }
+        error
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Error;
+    use std::io;
+
+    #[test]
+    fn build_with_source_note_and_code() {
+        let error = Error::builder()
+            .message("failed to reach the license server")
+            .source(io::Error::from(io::ErrorKind::TimedOut))
+            .note("check your network connection")
+            .code("E_LICENSE")
+            .capture_backtrace(false)
+            .build();
+
+        assert_eq!(error.to_string(), "failed to reach the license server");
+        let source = error.source().unwrap();
+        let io_error = source.downcast_ref::<io::Error>().unwrap();
+        assert_eq!(io_error.kind(), io::ErrorKind::TimedOut);
+        let debug = format!("{:?}", error);
+        assert!(debug.contains("\n\nNote: check your network connection\n"));
+        assert_eq!(error.code(), Some("E_LICENSE"));
+        assert!(!error.has_backtrace());
+    }
+
+    #[test]
+    fn build_message_only() {
+        let error = Error::builder().message("oh no").build();
+        assert_eq!(error.downcast_ref::<String>().unwrap(), "oh no");
+        assert!(error.source().is_none());
+        assert_eq!(error.code(), None);
+    }
+
+    #[test]
+    fn build_source_only() {
+        let error = Error::builder()
+            .source(io::Error::from(io::ErrorKind::NotFound))
+            .build();
+        assert!(error.is::<io::Error>());
+    }
+
+    #[test]
+    #[should_panic(expected = "without a message or a source")]
+    fn build_without_message_or_source() {
+        let _ = Error::builder().note("lonely note").build();
+    }
+}
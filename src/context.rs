@@ -1,9 +1,12 @@
-use crate::Error;
-use std::error::Error as StdError;
-use std::fmt::{self, Debug, Display};
+use crate::{Chain, Error};
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::error::Error as StdError;
+use core::fmt::{self, Debug, Display};
 
 #[cfg(backtrace)]
-use std::backtrace::Backtrace;
+use std::error::Request;
 
 /// Provides the `context` method for `Result`.
 ///
@@ -49,6 +52,11 @@ use std::backtrace::Backtrace;
 /// ```
 pub trait Context<T, E> {
     /// Wrap the error value with additional context.
+    ///
+    /// The context value is only used if the result is an error. On success
+    /// it is dropped without being formatted, and no allocation or backtrace
+    /// capture takes place. If computing the context value is itself
+    /// expensive, use [`with_context`][Context::with_context] instead.
     fn context<C>(self, context: C) -> Result<T, Error>
     where
         C: Display + Send + Sync + 'static;
@@ -59,6 +67,37 @@ pub trait Context<T, E> {
     where
         C: Display + Send + Sync + 'static,
         F: FnOnce() -> C;
+
+    /// Alias of [`context`][Context::context], under the name used by `eyre`.
+    fn wrap_err<C>(self, context: C) -> Result<T, Error>
+    where
+        Self: Sized,
+        C: Display + Send + Sync + 'static,
+    {
+        self.context(context)
+    }
+
+    /// Alias of [`with_context`][Context::with_context], under the name used
+    /// by `eyre`.
+    fn wrap_err_with<C, F>(self, f: F) -> Result<T, Error>
+    where
+        Self: Sized,
+        C: Display + Send + Sync + 'static,
+        F: FnOnce() -> C,
+    {
+        self.with_context(f)
+    }
+
+    /// Wrap the error value with additional context, and mark the error as
+    /// worth retrying as by [`Error::mark_retryable`].
+    fn retryable_context<C>(self, context: C) -> Result<T, Error>
+    where
+        Self: Sized,
+        C: Display + Send + Sync + 'static,
+    {
+        self.context(context)
+            .map_err(|error| error.mark_retryable(true))
+    }
 }
 
 impl<T, E> Context<T, E> for Result<T, E>
@@ -69,7 +108,7 @@ where
     where
         C: Display + Send + Sync + 'static,
     {
-        self.map_err(|error| Error::from(ContextError { error, context }))
+        self.map_err(|error| Error::from_context(context, error))
     }
 
     fn with_context<C, F>(self, context: F) -> Result<T, Error>
@@ -77,12 +116,7 @@ where
         C: Display + Send + Sync + 'static,
         F: FnOnce() -> C,
     {
-        self.map_err(|error| {
-            Error::from(ContextError {
-                error,
-                context: context(),
-            })
-        })
+        self.map_err(|error| Error::from_context(context(), error))
     }
 }
 
@@ -103,6 +137,75 @@ impl<T> Context<T, Error> for Result<T, Error> {
     }
 }
 
+/// Provides the `context_local` method for `Result` with an error type that
+/// is not `Send` or `Sync`.
+///
+/// [`Context`] and the conversion into [`Error`] by `?` need the error to be
+/// `Send + Sync`, as `Error` is. This trait makes an `Error` of any other
+/// error by formatting it right away: the `Display` string of the error and
+/// of each of its sources is kept in place of the error itself.
+///
+/// The type information is lost on the way, so the result cannot be
+/// downcast to the original error type or its sources, and anything that
+/// they provide, such as a backtrace, is not available. The messages of the
+/// chain are preserved.
+///
+/// # Example
+///
+/// ```
+/// use anyhow::{ContextLocal, Result};
+/// use std::cell::Cell;
+/// use std::error::Error as StdError;
+/// use std::fmt::{self, Display};
+///
+/// // Not Sync, because of the Cell.
+/// #[derive(Debug)]
+/// struct LegacyError {
+///     code: Cell<i32>,
+/// }
+///
+/// impl Display for LegacyError {
+///     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+///         write!(f, "legacy error {}", self.code.get())
+///     }
+/// }
+///
+/// impl StdError for LegacyError {}
+///
+/// fn legacy_call() -> Result<(), LegacyError> {
+///     Err(LegacyError { code: Cell::new(7) })
+/// }
+///
+/// fn run() -> Result<()> {
+///     legacy_call().context_local("failed to run legacy call")?;
+///     Ok(())
+/// }
+/// #
+/// # fn main() {
+/// #     let error = run().unwrap_err();
+/// #     assert!(error.chain_eq(&["failed to run legacy call", "legacy error 7"]));
+/// # }
+/// ```
+pub trait ContextLocal<T> {
+    /// Wrap the error value with additional context, formatting the error
+    /// into an owned message first.
+    fn context_local<C>(self, context: C) -> Result<T, Error>
+    where
+        C: Display + Send + Sync + 'static;
+}
+
+impl<T, E> ContextLocal<T> for Result<T, E>
+where
+    E: StdError + 'static,
+{
+    fn context_local<C>(self, context: C) -> Result<T, Error>
+    where
+        C: Display + Send + Sync + 'static,
+    {
+        self.map_err(|error| Error::from_context(context, LocalError::new(&error)))
+    }
+}
+
 pub(crate) struct ContextError<E, C> {
     pub error: E,
     pub context: C,
@@ -133,8 +236,8 @@ where
     C: Display,
 {
     #[cfg(backtrace)]
-    fn backtrace(&self) -> Option<&Backtrace> {
-        self.error.backtrace()
+    fn provide<'a>(&'a self, request: &mut Request<'a>) {
+        self.error.provide(request);
     }
 
     fn source(&self) -> Option<&(dyn StdError + 'static)> {
@@ -147,11 +250,56 @@ where
     C: Display,
 {
     #[cfg(backtrace)]
-    fn backtrace(&self) -> Option<&Backtrace> {
-        Some(self.error.backtrace())
+    fn provide<'a>(&'a self, request: &mut Request<'a>) {
+        self.error.provide(request);
     }
 
     fn source(&self) -> Option<&(dyn StdError + 'static)> {
         Some(&*self.error)
     }
 }
+
+// The messages of an error that is not Send and Sync, kept in its place by
+// ContextLocal, of an error decoded by Error::decode, or those produced by
+// Error::map_messages, with one LocalError per link of the original chain.
+#[derive(Debug)]
+pub(crate) struct LocalError {
+    message: String,
+    source: Option<Box<LocalError>>,
+}
+
+impl LocalError {
+    fn new(error: &(dyn StdError + 'static)) -> Self {
+        LocalError::from_messages(Chain::new(error).map(|link| link.to_string()).collect())
+    }
+
+    // The messages are outermost first. There must be at least one.
+    pub(crate) fn from_messages(mut messages: Vec<String>) -> Self {
+        let mut local = LocalError {
+            message: messages.pop().unwrap(),
+            source: None,
+        };
+        while let Some(message) = messages.pop() {
+            local = LocalError {
+                message,
+                source: Some(Box::new(local)),
+            };
+        }
+        local
+    }
+}
+
+impl Display for LocalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl StdError for LocalError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match &self.source {
+            Some(source) => Some(&**source),
+            None => None,
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use crate::context::LocalError;
+use crate::Error;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::error::Error as StdError;
+use core::fmt::{self, Display};
+use core::str;
+
+// Version of the encoding, written as the first byte so that the format can be
+// changed without misreading the output of older versions.
+const VERSION: u8 = 1;
+
+impl Error {
+    /// Encode the messages of this error in a compact binary form, for
+    /// sending it to another process.
+    ///
+    /// The encoding holds the `Display` string of every error in
+    /// [`chain()`][Error::chain] and nothing else, and is read back by
+    /// [`decode`][Error::decode]. It consists of a version byte, the number of
+    /// messages, and each message as UTF-8 preceded by its length in bytes.
+    /// The numbers are 32-bit little-endian.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anyhow::{anyhow, Error};
+    ///
+    /// let error = anyhow!("disk full").context("failed to save");
+    /// let bytes = error.encode();
+    ///
+    /// let decoded = Error::decode(&bytes).unwrap();
+    /// assert!(decoded.chain_eq(&["failed to save", "disk full"]));
+    /// ```
+    pub fn encode(&self) -> Vec<u8> {
+        let messages: Vec<String> = self.chain().map(|error| error.to_string()).collect();
+        let len = messages
+            .iter()
+            .map(|message| 4 + message.len())
+            .sum::<usize>();
+        let mut bytes = Vec::with_capacity(5 + len);
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&(messages.len() as u32).to_le_bytes());
+        for message in &messages {
+            bytes.extend_from_slice(&(message.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(message.as_bytes());
+        }
+        bytes
+    }
+
+    /// Reconstruct an error from the output of [`encode`][Error::encode].
+    ///
+    /// The decoded error has the same chain of messages as the error that was
+    /// encoded, but none of its types: it cannot be downcast to any of the
+    /// original errors, and none of the chain is
+    /// [context][Error::is_context_frame]. Notes, codes and the backtrace are
+    /// not part of the encoding, and a new backtrace is captured here as for
+    /// any other error.
+    pub fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut reader = Reader(bytes);
+        if reader.take(1)? != [VERSION] {
+            return Err(DecodeError::new("unsupported version"));
+        }
+        let count = reader.u32()?;
+        if count == 0 {
+            return Err(DecodeError::new("no messages"));
+        }
+        let mut messages = Vec::new();
+        for _ in 0..count {
+            let len = reader.u32()? as usize;
+            let message = str::from_utf8(reader.take(len)?)
+                .map_err(|_| DecodeError::new("message is not valid UTF-8"))?;
+            messages.push(message.to_string());
+        }
+        if !reader.0.is_empty() {
+            return Err(DecodeError::new("trailing bytes"));
+        }
+        Ok(Error::new(LocalError::from_messages(messages)))
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if self.0.len() < len {
+            return Err(DecodeError::new("unexpected end of input"));
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> Result<u32, DecodeError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+}
+
+/// The error returned by [`Error::decode`] for input that is not the output
+/// of [`Error::encode`].
+#[derive(Clone, Debug)]
+pub struct DecodeError {
+    reason: &'static str,
+}
+
+impl DecodeError {
+    fn new(reason: &'static str) -> Self {
+        DecodeError { reason }
+    }
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid encoded error: {}", self.reason)
+    }
+}
+
+impl StdError for DecodeError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::{anyhow, Error};
+    use std::io;
+
+    #[test]
+    fn round_trip() {
+        let error = Error::new(io::Error::new(io::ErrorKind::NotFound, "oh no!"))
+            .context("failed to read config")
+            .context("failed to start");
+        let bytes = error.encode();
+
+        let decoded = Error::decode(&bytes).unwrap();
+        assert_eq!(decoded.into_chain_strings(), error.into_chain_strings());
+    }
+
+    #[test]
+    fn invalid() {
+        let bytes = anyhow!("oh no!").encode();
+
+        assert!(Error::decode(&[]).is_err());
+        assert!(Error::decode(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Error::decode(&[bytes.as_slice(), &[0]].concat()).is_err());
+        assert!(Error::decode(&[2, 0, 0, 0, 0]).is_err());
+        assert!(Error::decode(&[1, 0, 0, 0, 0]).is_err());
+
+        let error = Error::decode(&[1, 1, 0, 0, 0, 1, 0, 0, 0, 0xFF]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid encoded error: message is not valid UTF-8",
+        );
+    }
+}
@@ -1,13 +1,54 @@
-use crate::context::ContextError;
-use std::any::TypeId;
-use std::error::Error as StdError;
-use std::fmt::{self, Debug, Display};
-use std::mem;
-use std::ops::{Deref, DerefMut};
-use std::ptr;
+use crate::builder::ErrorBuilder;
+use crate::context::{ContextError, LocalError};
+use crate::template::ErrorTemplate;
+#[cfg(feature = "build-info")]
+use crate::Origin;
+use crate::{Labels, Severity, BACKTRACE_HINT, SHOW_TYPES};
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::any::{type_name, Any, TypeId};
+use core::error::Error as StdError;
+use core::fmt::{self, Debug, Display};
+use core::iter::FusedIterator;
+use core::mem;
+use core::ops::{Deref, DerefMut};
+use core::panic::Location;
+use core::ptr;
+use core::slice;
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
 
+#[cfg(feature = "std")]
+use crate::{HOOK, LABELS};
+#[cfg(all(feature = "std", not(backtrace)))]
+use std::env;
+#[cfg(all(feature = "std", not(backtrace)))]
+use std::ffi::OsString;
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "std")]
+use std::sync::PoisonError;
+#[cfg(feature = "std")]
+use std::thread::ThreadId;
+
+#[cfg(feature = "build-info")]
+use std::process;
+#[cfg(feature = "thread-info")]
+use std::thread::{self, Thread};
+#[cfg(feature = "timestamp")]
+use std::time::SystemTime;
+#[cfg(feature = "tracing")]
+use tracing::{Metadata, Span};
+
+#[cfg(backtrace)]
+use crate::backtrace::{self, BacktraceFrame};
+#[cfg(backtrace)]
+use crate::{backtrace_filter, BACKTRACE_FRAME_LIMIT, RESOLVE_BACKTRACE, TRIM_BACKTRACE};
 #[cfg(backtrace)]
 use std::backtrace::{Backtrace, BacktraceStatus};
+#[cfg(backtrace)]
+use std::error::{self, Request};
 
 /// The `Error` type, a wrapper around a dynamic error type.
 ///
@@ -18,8 +59,15 @@ use std::backtrace::{Backtrace, BacktraceStatus};
 /// - `Error` guarantees that a backtrace is available, even if the underlying
 ///   error type does not provide one.
 /// - `Error` is represented as a narrow pointer &mdash; exactly one word in
-///   size instead of two.
+///   size instead of two. The pointer is never null, so `Option<Error>` and
+///   `Result<(), Error>` are one word as well.
 pub struct Error {
+    // Every error, including a short ad-hoc message, lives behind this one
+    // allocation. Storing small messages inline was considered but would need
+    // at least three words for the bytes plus a tag, giving up the one-word
+    // size that keeps `Result<T, Error>` cheap to return on the success path.
+    // Error paths are expected to be cold enough that one allocation is the
+    // better trade.
     inner: Box<ErrorImpl<()>>,
 }
 
@@ -38,34 +86,361 @@ impl Error {
         // Captured here instead of in Error::construct to have one fewer layer
         // of wrapping visible in the backtrace.
         #[cfg(backtrace)]
-        let backtrace = match error.backtrace() {
+        let backtrace = match error::request_ref::<Backtrace>(&error) {
+            Some(_) => None,
+            None => Some(capture_backtrace(&error)),
+        };
+
+        #[cfg(not(backtrace))]
+        let backtrace = None;
+
+        Error::construct(error, TypeId::of::<E>(), type_name::<E>(), backtrace)
+    }
+
+    /// Create a new error object from a boxed error of unknown type.
+    ///
+    /// The box is stored as is rather than boxed a second time, and
+    /// [`into_inner`][Error::into_inner] gives it back. It can also be
+    /// recovered by downcasting to `Box<dyn std::error::Error + Send + Sync>`.
+    ///
+    /// If the boxed error does not provide a backtrace, a backtrace will be
+    /// created here to ensure that a backtrace exists.
+    pub fn from_boxed(error: Box<dyn StdError + Send + Sync + 'static>) -> Self {
+        let error = BoxedError(error);
+
+        #[cfg(backtrace)]
+        let backtrace = match error::request_ref::<Backtrace>(&error) {
+            Some(_) => None,
+            None => Some(capture_backtrace(&*error.0)),
+        };
+
+        #[cfg(not(backtrace))]
+        let backtrace = None;
+
+        let type_id = TypeId::of::<Box<dyn StdError + Send + Sync>>();
+        let type_name = type_name::<Box<dyn StdError + Send + Sync>>();
+        Error::construct(error, type_id, type_name, backtrace)
+    }
+
+    /// Start building an error with more options than [`new`][Error::new] and
+    /// [`msg`][Error::msg] provide.
+    ///
+    /// See [`ErrorBuilder`] for the available options.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anyhow::Error;
+    /// use std::io;
+    ///
+    /// let error = Error::builder()
+    ///     .message("failed to reach the license server")
+    ///     .source(io::Error::from(io::ErrorKind::TimedOut))
+    ///     .note("Check your network connection")
+    ///     .code("E_LICENSE")
+    ///     .build();
+    /// ```
+    pub fn builder() -> ErrorBuilder {
+        ErrorBuilder::new()
+    }
+
+    /// Create a factory for errors with the given message, for sentinel errors
+    /// that are returned from many places.
+    ///
+    /// See [`ErrorTemplate`] for how this differs from cloning an error.
+    pub const fn template(message: &'static str) -> ErrorTemplate {
+        ErrorTemplate::new(message)
+    }
+
+    // Like Error::new, but without capturing a backtrace. A disabled backtrace
+    // is stored instead so that Error::backtrace still has one to return.
+    pub(crate) fn new_without_backtrace<E>(error: E) -> Self
+    where
+        E: StdError + Send + Sync + 'static,
+    {
+        #[cfg(backtrace)]
+        let backtrace = match error::request_ref::<Backtrace>(&error) {
             Some(_) => None,
-            None => Some(Backtrace::capture()),
+            None => Some(Backtrace::disabled()),
+        };
+
+        #[cfg(not(backtrace))]
+        let backtrace = None;
+
+        Error::construct(error, TypeId::of::<E>(), type_name::<E>(), backtrace)
+    }
+
+    /// Create a new error object from a printable error message.
+    ///
+    /// This is the function form of the [`anyhow!`][crate::anyhow] macro. If
+    /// the argument implements `std::error::Error`, prefer `Error::new` instead
+    /// which preserves the underlying error's cause chain and backtrace.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anyhow::{Error, Result};
+    ///
+    /// fn lookup(key: &str) -> Result<u32> {
+    ///     # const IGNORE: &str = stringify! {
+    ///     ...
+    ///     # };
+    ///     Err(Error::msg(format!("no such key: {}", key)))
+    /// }
+    /// ```
+    pub fn msg<M>(message: M) -> Self
+    where
+        M: Display + Debug + Send + Sync + 'static,
+    {
+        Error::new_adhoc_captured(message)
+    }
+
+    /// Create a new error object from a value that implements `Display` but
+    /// not necessarily `Debug`.
+    ///
+    /// This is like [`Error::msg`] for types that only implement `Display`,
+    /// such as some third-party message types. The value is used for both the
+    /// `Display` and the `Debug` representation of the error, and it can be
+    /// recovered by [`downcast`][Error::downcast] like any other message.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anyhow::Error;
+    /// use std::fmt::{self, Display};
+    ///
+    /// struct Status(u16);
+    ///
+    /// impl Display for Status {
+    ///     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    ///         write!(f, "server returned status {}", self.0)
+    ///     }
+    /// }
+    ///
+    /// let error = Error::from_display(Status(503));
+    /// assert_eq!(error.to_string(), "server returned status 503");
+    /// ```
+    pub fn from_display<D>(value: D) -> Self
+    where
+        D: Display + Send + Sync + 'static,
+    {
+        let error = MessageError(DisplayError(value));
+
+        #[cfg(backtrace)]
+        let backtrace = Some(capture_backtrace(&error));
+
+        #[cfg(not(backtrace))]
+        let backtrace = None;
+
+        // DisplayError is transparent, so the error can still be downcast to
+        // D using D's TypeId.
+        Error::construct(error, TypeId::of::<D>(), type_name::<D>(), backtrace)
+    }
+
+    /// Create an error grouping several errors that occurred together, such
+    /// as one per invalid field of a form.
+    ///
+    /// The errors are not causes of one another: the new error has no
+    /// [`source`][std::error::Error::source], displays as the number of
+    /// errors, and keeps them as its [`sub_errors`][Error::sub_errors]. The
+    /// `Debug` representation lists them under an "Errors:" heading, numbered,
+    /// with the messages of each one's chain joined by `: `.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anyhow::{anyhow, Error};
+    ///
+    /// let error = Error::from_errors([
+    ///     anyhow!("name is empty"),
+    ///     anyhow!("age is negative"),
+    /// ]);
+    /// assert_eq!(error.to_string(), "2 errors occurred");
+    /// assert_eq!(error.sub_errors().len(), 2);
+    /// ```
+    pub fn from_errors<I>(errors: I) -> Self
+    where
+        I: IntoIterator<Item = Error>,
+    {
+        Error::new(AggregateError {
+            header: None,
+            errors: errors.into_iter().collect(),
+        })
+    }
+
+    /// Create an error grouping several errors, as by
+    /// [`from_errors`][Error::from_errors], that displays as the given header
+    /// instead of the number of errors.
+    ///
+    /// The header is the outermost message, the one shown by `Display` and
+    /// first in the `Debug` representation, with the sub-errors numbered
+    /// beneath it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anyhow::{anyhow, Error};
+    ///
+    /// fn validate(name: &str, age: i32) -> Result<(), Error> {
+    ///     let mut errors = Vec::new();
+    ///     if name.is_empty() {
+    ///         errors.push(anyhow!("name is empty"));
+    ///     }
+    ///     if age < 0 {
+    ///         errors.push(anyhow!("age is negative"));
+    ///     }
+    ///     if errors.is_empty() {
+    ///         return Ok(());
+    ///     }
+    ///     let header = format!("{} validation errors occurred", errors.len());
+    ///     Err(Error::from_errors_with(header, errors))
+    /// }
+    /// #
+    /// # let error = validate("", -1).unwrap_err();
+    /// # assert_eq!(error.to_string(), "2 validation errors occurred");
+    /// ```
+    pub fn from_errors_with<I, H>(header: H, errors: I) -> Self
+    where
+        I: IntoIterator<Item = Error>,
+        H: Display + Send + Sync + 'static,
+    {
+        Error::new(AggregateError {
+            header: Some(Box::new(header)),
+            errors: errors.into_iter().collect(),
+        })
+    }
+
+    /// Create a new error object from a message computed by a closure.
+    ///
+    /// The closure is called exactly once, here. This is for building a
+    /// standalone error whose message is derived from data that is only
+    /// borrowed; for adding a lazily computed message to an existing error,
+    /// see [`Context::with_context`][crate::Context::with_context].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anyhow::Error;
+    /// use std::collections::HashMap;
+    ///
+    /// fn missing_keys(expected: &[&str], map: &HashMap<String, u32>) -> Error {
+    ///     Error::new_with(|| {
+    ///         let missing: Vec<_> = expected
+    ///             .iter()
+    ///             .filter(|key| !map.contains_key(**key))
+    ///             .collect();
+    ///         format!("missing keys: {:?}", missing)
+    ///     })
+    /// }
+    /// ```
+    pub fn new_with<F, M>(f: F) -> Self
+    where
+        F: FnOnce() -> M,
+        M: Display + Debug + Send + Sync + 'static,
+    {
+        Error::new_adhoc_captured(f())
+    }
+
+    /// Create an error object from the payload of a caught panic.
+    ///
+    /// This pairs with [`std::panic::catch_unwind`]. If the payload is a
+    /// `&'static str` or `String`, as it is for panics raised by the `panic!`
+    /// macro, the error's message is the panic message. Otherwise the payload
+    /// is discarded and the message is "panic occurred".
+    ///
+    /// The backtrace, if any, is captured here rather than at the point of the
+    /// panic.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anyhow::Error;
+    /// use std::panic;
+    ///
+    /// let result = panic::catch_unwind(|| {
+    ///     panic!("boom");
+    /// });
+    ///
+    /// let error = Error::from_panic(result.unwrap_err());
+    /// assert_eq!(error.to_string(), "boom");
+    /// ```
+    pub fn from_panic(payload: Box<dyn Any + Send>) -> Self {
+        let message = match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(payload) => match payload.downcast_ref::<&'static str>() {
+                Some(message) => (*message).to_string(),
+                None => "panic occurred".to_string(),
+            },
         };
 
+        Error::new_adhoc_captured(message)
+    }
+
+    // An ad-hoc error with a backtrace captured here, subject to the filter
+    // installed by set_backtrace_filter.
+    pub(crate) fn new_adhoc_captured<M>(message: M) -> Self
+    where
+        M: Display + Debug + Send + Sync + 'static,
+    {
+        let error = MessageError(message);
+
+        #[cfg(backtrace)]
+        let backtrace = Some(capture_backtrace(&error));
+
         #[cfg(not(backtrace))]
         let backtrace = None;
 
-        Error::construct(error, TypeId::of::<E>(), backtrace)
+        Error::construct(error, TypeId::of::<M>(), type_name::<M>(), backtrace)
     }
 
     pub(crate) fn new_adhoc<M>(message: M, backtrace: Option<Backtrace>) -> Self
     where
         M: Display + Debug + Send + Sync + 'static,
     {
-        Error::construct(MessageError(message), TypeId::of::<M>(), backtrace)
+        let type_id = TypeId::of::<M>();
+        Error::construct(MessageError(message), type_id, type_name::<M>(), backtrace)
     }
 
-    fn construct<E>(error: E, type_id: TypeId, backtrace: Option<Backtrace>) -> Self
+    // The type_id and type_name are of the type that the error can be
+    // downcast to, or of the context for a layer of context.
+    fn construct<E>(
+        error: E,
+        type_id: TypeId,
+        type_name: &'static str,
+        backtrace: Option<Backtrace>,
+    ) -> Self
     where
         E: StdError + Send + Sync + 'static,
     {
         unsafe {
             let obj = mem::transmute::<&dyn StdError, TraitObject>(&error);
+            // The type_id differs from that of E only for a MessageError<M>,
+            // which has that of M, and a BoxedError, which has that of the box.
+            let message =
+                type_id != TypeId::of::<E>() && TypeId::of::<E>() != TypeId::of::<BoxedError>();
             let inner = Box::new(ErrorImpl {
                 vtable: obj.vtable,
+                object_type_id: TypeId::of::<E>(),
                 type_id,
+                type_name,
+                message,
+                into_boxed: object_into_boxed::<E>,
                 backtrace,
+                attachments: None,
+                location: None,
+                root_cause: RootCause::new(),
+                #[cfg(feature = "timestamp")]
+                created_at: SystemTime::now(),
+                #[cfg(feature = "thread-info")]
+                origin_thread: thread::current(),
+                #[cfg(feature = "build-info")]
+                origin: Origin {
+                    pid: process::id(),
+                    version: env!("CARGO_PKG_VERSION"),
+                },
+                #[cfg(feature = "tracing")]
+                span: Span::current().metadata(),
+                context: None,
                 error,
             });
             Error {
@@ -80,6 +455,10 @@ impl Error {
     /// [`Context`][crate::Context] extension trait may be more convenient than
     /// this function.
     ///
+    /// No new backtrace is captured for the context. The backtrace returned by
+    /// `backtrace()` remains the one captured when the underlying error was
+    /// created, however many layers of context are added.
+    ///
     /// The primary reason to use `error.context(...)` instead of
     /// `result.context(...)` via the `Context` trait would be if the context
     /// needs to depend on some data held by the underlying error:
@@ -127,385 +506,4899 @@ impl Error {
     ///     })
     /// }
     /// ```
-    pub fn context<C>(self, context: C) -> Self
+    pub fn context<C>(mut self, context: C) -> Self
     where
         C: Display + Send + Sync + 'static,
     {
-        Error::from(ContextError {
-            error: self,
-            context,
-        })
+        // Ad-hoc messages are common enough to be worth a fast path that
+        // stores the message and the context in one allocation rather than
+        // boxing the context around the existing allocation.
+        if self.inner.object_type_id == TypeId::of::<MessageError<String>>() {
+            return unsafe { self.message_context::<String, C>(context) };
+        }
+        if self.inner.object_type_id == TypeId::of::<MessageError<&'static str>>() {
+            return unsafe { self.message_context::<&'static str, C>(context) };
+        }
+
+        let carried = self.inner.take_carried();
+        self.inner.root_cause.clear();
+        // The Error being wrapped always provides a backtrace, so there is no
+        // need to ask for it as Error::new would, which takes time and stack
+        // proportional to the number of layers beneath.
+        let mut error = Error::construct(
+            ContextError {
+                error: self,
+                context,
+            },
+            TypeId::of::<ContextError<Error, C>>(),
+            type_name::<C>(),
+            None,
+        );
+        error.inner.context = Some(ContextVtable {
+            inner_error: context_anyhow_inner_error::<C>,
+            inner_type_id: TypeId::of::<Error>(),
+            inner_type_name: type_name::<Error>(),
+            source_mut: context_anyhow_source_mut::<C>,
+            into_source: context_anyhow_into_source::<C>,
+            take_inner: context_anyhow_take_inner::<C>,
+            context_value: context_value::<C, Error>,
+        });
+        error.inner.set_carried(carried);
+        error
     }
 
-    /// Get the backtrace for this Error.
+    /// Wrap the error value with additional context that is formatted right
+    /// away.
     ///
-    /// Backtraces are only available on the nightly channel. Tracking issue:
-    /// [rust-lang/rust#53487][tracking].
+    /// Unlike [`context`][Error::context], `context` need not be `'static`,
+    /// `Send` or `Sync`: it is rendered with `Display` into an owned `String`
+    /// and only that string is kept. It can therefore borrow from locals, at
+    /// the cost of allocating the string up front. `context` instead stores
+    /// the typed value itself and defers formatting it until the error is
+    /// displayed.
     ///
-    /// In order for the backtrace to be meaningful, the environment variable
-    /// `RUST_LIB_BACKTRACE=1` must be defined. Backtraces are somewhat
-    /// expensive to capture in Rust, so we don't necessarily want to be
-    /// capturing them all over the place all the time.
+    /// # Example
     ///
-    /// [tracking]: https://github.com/rust-lang/rust/issues/53487
-    #[cfg(backtrace)]
-    pub fn backtrace(&self) -> &Backtrace {
-        // NB: this unwrap can only fail if the underlying error's backtrace
-        // method is nondeterministic, which would only happen in maliciously
-        // constructed code
-        self.inner
-            .backtrace
-            .as_ref()
-            .or_else(|| self.inner.error().backtrace())
-            .expect("backtrace capture failed")
+    /// ```
+    /// use anyhow::anyhow;
+    ///
+    /// fn load(name: &str) -> anyhow::Result<()> {
+    ///     let error = anyhow!("file not found");
+    ///     Err(error.context_owned(format_args!("failed to load {}", name)))
+    /// }
+    ///
+    /// let error = load("config.toml").unwrap_err();
+    /// assert_eq!(error.to_string(), "failed to load config.toml");
+    /// ```
+    pub fn context_owned<C>(self, context: C) -> Self
+    where
+        C: Display,
+    {
+        self.context(context.to_string())
     }
 
-    /// An iterator of the chain of source errors contained by this Error.
+    /// Replace the message shown for this error, keeping the error beneath
+    /// it as its cause.
     ///
-    /// This iterator will visit every error in the cause chain of this error
-    /// object, beginning with the error that this error object was created
-    /// from.
+    /// This is structurally the same as [`context`][Error::context]: `message`
+    /// becomes the outermost link, shown by `Display`, and the existing error
+    /// follows it in [`chain()`][Error::chain] and in the `Debug`
+    /// representation. It states a different intent, that of presenting a
+    /// more general message to users while the full chain stays available for
+    /// logs.
     ///
     /// # Example
     ///
     /// ```
-    /// use anyhow::Error;
-    /// use std::io;
+    /// use anyhow::anyhow;
     ///
-    /// pub fn underlying_io_error_kind(error: &Error) -> Option<io::ErrorKind> {
-    ///     for cause in error.chain() {
-    ///         if let Some(io_error) = cause.downcast_ref::<io::Error>() {
-    ///             return Some(io_error.kind());
-    ///         }
-    ///     }
-    ///     None
-    /// }
+    /// let error = anyhow!("connection to 10.0.0.7:5432 refused")
+    ///     .with_message("the service is temporarily unavailable");
+    /// assert_eq!(error.to_string(), "the service is temporarily unavailable");
+    /// assert_eq!(error.chain().count(), 2);
     /// ```
-    pub fn chain(&self) -> Chain {
-        Chain {
-            next: Some(self.inner.error()),
-        }
+    pub fn with_message<M>(self, message: M) -> Self
+    where
+        M: Display + Send + Sync + 'static,
+    {
+        self.context(message)
     }
 
-    /// The lowest level cause of this error &mdash; this error's cause's
-    /// cause's cause etc.
+    /// Wrap the error value with additional context, recording the given
+    /// source location as where the context was added.
     ///
-    /// The root cause is the last error in the iterator produced by
-    /// [`chain()`][Error::chain].
-    pub fn root_cause(&self) -> &(dyn StdError + 'static) {
-        let mut chain = self.chain();
-        let mut root_cause = chain.next().unwrap();
-        for cause in chain {
-            root_cause = cause;
-        }
-        root_cause
+    /// This is the same as [`context`][Error::context] except for the
+    /// location, which is kept with the new layer of context and can be read
+    /// back with [`link_location`][Error::link_location]. It is for code that
+    /// knows the location it wants to report, such as a macro that forwards
+    /// the location of its own caller, rather than the location of the call
+    /// to `context_at` itself.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anyhow::{anyhow, Error};
+    /// use std::panic::Location;
+    ///
+    /// #[track_caller]
+    /// fn while_loading(error: Error) -> Error {
+    ///     error.context_at("failed to load config", Location::caller())
+    /// }
+    ///
+    /// let error = while_loading(anyhow!("file not found"));
+    /// let location = error.link_location(&*error).unwrap();
+    /// assert_eq!(location.file(), file!());
+    /// ```
+    pub fn context_at<C>(self, context: C, location: &'static Location<'static>) -> Self
+    where
+        C: Display + Send + Sync + 'static,
+    {
+        let mut error = self.context(context);
+        error.inner.location = Some(location);
+        error
     }
 
-    /// Returns `true` if `E` is the type wrapped by this error object.
-    pub fn is<E>(&self) -> bool
+    // Safety: the error must be stored as a MessageError<M>.
+    unsafe fn message_context<M, C>(self, context: C) -> Self
     where
-        E: Display + Debug + Send + Sync + 'static,
+        M: Display + Debug + Send + Sync + 'static,
+        C: Display + Send + Sync + 'static,
     {
-        TypeId::of::<E>() == self.inner.type_id
+        let inner = ptr::read(&self.inner);
+        mem::forget(self);
+        let inner = mem::transmute::<Box<ErrorImpl<()>>, Box<ErrorImpl<MessageError<M>>>>(inner);
+        let mut inner = *inner;
+        let carried = inner.take_carried();
+        let error = ContextError {
+            error: inner.error,
+            context,
+        };
+        let mut error = Error::construct(
+            error,
+            TypeId::of::<ContextError<MessageError<M>, C>>(),
+            type_name::<C>(),
+            inner.backtrace,
+        );
+        error.inner.context = Some(ContextVtable {
+            inner_error: no_inner_error,
+            inner_type_id: TypeId::of::<M>(),
+            inner_type_name: type_name::<M>(),
+            source_mut: context_message_source_mut::<C, M>,
+            into_source: context_message_into_source::<C, M>,
+            take_inner: no_take_inner,
+            context_value: context_value::<C, MessageError<M>>,
+        });
+        error.inner.set_carried(carried);
+        error
     }
 
-    /// Attempt to downcast the error object to a concrete type.
-    pub fn downcast<E>(self) -> Result<E, Self>
+    pub(crate) fn from_context<C, E>(context: C, error: E) -> Self
     where
-        E: Display + Debug + Send + Sync + 'static,
+        C: Display + Send + Sync + 'static,
+        E: StdError + Send + Sync + 'static,
     {
-        if let Some(error) = self.downcast_ref::<E>() {
-            unsafe {
-                let error = ptr::read(error);
-                drop(ptr::read(&self.inner));
-                mem::forget(self);
-                Ok(error)
-            }
-        } else {
-            Err(self)
-        }
+        let mut error = Error::from(ContextError { error, context });
+        error.inner.type_name = type_name::<C>();
+        error.inner.context = Some(ContextVtable {
+            inner_error: no_inner_error,
+            inner_type_id: TypeId::of::<E>(),
+            inner_type_name: type_name::<E>(),
+            source_mut: context_source_mut::<C, E>,
+            into_source: context_into_source::<C, E>,
+            take_inner: no_take_inner,
+            context_value: context_value::<C, E>,
+        });
+        error
     }
 
-    /// Downcast this error object by reference.
+    /// Declare that this error was caused by `source`, placing it beneath the
+    /// current root cause.
+    ///
+    /// This is the opposite direction from [`context`][Error::context]: rather
+    /// than adding a new outermost layer, it extends the bottom of the chain.
+    /// After this call, `source` and then its own sources appear last in
+    /// [`chain()`][Error::chain] and `source` or its deepest source becomes
+    /// the [`root_cause()`][Error::root_cause].
+    ///
+    /// The errors already in the chain cannot be modified to point at the new
+    /// source, so it is held by this `Error` instead and spliced in by the
+    /// chain iterator. As a consequence it is visible through `chain()`,
+    /// `root_cause()` and the `Debug` representation, but not by calling
+    /// `source()` directly on the deepest of the existing errors.
     ///
     /// # Example
     ///
     /// ```
-    /// # use anyhow::anyhow;
-    /// # use std::fmt::{self, Display};
-    /// # use std::task::Poll;
-    /// #
-    /// # #[derive(Debug)]
-    /// # enum DataStoreError {
-    /// #     Censored(()),
-    /// # }
-    /// #
-    /// # impl Display for DataStoreError {
-    /// #     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-    /// #         unimplemented!()
-    /// #     }
-    /// # }
-    /// #
-    /// # impl std::error::Error for DataStoreError {}
-    /// #
-    /// # const REDACTED_CONTENT: () = ();
-    /// #
-    /// # let error = anyhow!("...");
-    /// # let root_cause = &error;
+    /// use anyhow::anyhow;
+    /// use std::io;
+    ///
+    /// let error = anyhow!("failed to load plugin");
+    /// let io_error = io::Error::new(io::ErrorKind::NotFound, "libplugin.so not found");
+    ///
+    /// let error = error.wrap_source(io_error);
+    /// assert_eq!(error.root_cause().to_string(), "libplugin.so not found");
+    /// ```
+    pub fn wrap_source<E>(mut self, source: E) -> Self
+    where
+        E: StdError + Send + Sync + 'static,
+    {
+        self.inner.attachments_mut().sources.push(Box::new(source));
+        self.inner.root_cause.clear();
+        self
+    }
+
+    /// Attach a user-facing note to the error, such as a suggestion for how to
+    /// resolve it.
+    ///
+    /// Notes are not errors and do not appear in [`chain()`][Error::chain].
+    /// They are rendered by the `Debug` representation after the list of
+    /// causes, in the order they were attached.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anyhow::{anyhow, Error};
+    ///
+    /// fn connect() -> Result<(), Error> {
+    ///     # const IGNORE: &str = stringify! {
+    ///     ...
+    ///     # };
+    ///     Err(anyhow!("connection refused").note("Try running with --verbose"))
+    /// }
+    /// ```
+    ///
+    /// ```console
+    /// Error: connection refused
+    ///
+    /// Note: Try running with --verbose
+    /// ```
+    pub fn note<N>(mut self, note: N) -> Self
+    where
+        N: Display + Send + Sync + 'static,
+    {
+        self.inner.attachments_mut().notes.push(Box::new(note));
+        self
+    }
+
+    /// The time at which this error was created.
+    ///
+    /// Adding [`context`][Error::context] does not change the creation time,
+    /// so this is when the innermost error was turned into an `Error`.
+    ///
+    /// This method is only available if the `timestamp` Cargo feature is
+    /// enabled, as reading the clock is not free.
+    #[cfg(feature = "timestamp")]
+    pub fn created_at(&self) -> SystemTime {
+        self.inner.created_at
+    }
+
+    /// The ID of the thread on which this error was created.
+    ///
+    /// Adding [`context`][Error::context] does not change the origin thread,
+    /// so this is where the innermost error was turned into an `Error`.
+    ///
+    /// The thread is only recorded if the `thread-info` Cargo feature is
+    /// enabled. Otherwise this always returns `None`.
+    ///
+    /// This method is only available if the `std` Cargo feature is enabled.
+    #[cfg(feature = "std")]
+    pub fn origin_thread(&self) -> Option<ThreadId> {
+        #[cfg(feature = "thread-info")]
+        return Some(self.inner.origin_thread.id());
+
+        #[cfg(not(feature = "thread-info"))]
+        return None;
+    }
+
+    /// The name of the thread on which this error was created, if it has one.
+    ///
+    /// The thread is only recorded if the `thread-info` Cargo feature is
+    /// enabled. Otherwise this always returns `None`.
+    ///
+    /// This method is only available if the `std` Cargo feature is enabled.
+    #[cfg(feature = "std")]
+    pub fn origin_thread_name(&self) -> Option<&str> {
+        #[cfg(feature = "thread-info")]
+        return self.inner.origin_thread.name();
+
+        #[cfg(not(feature = "thread-info"))]
+        return None;
+    }
+
+    /// The process ID and the version of anyhow with which this error was
+    /// created.
+    ///
+    /// Adding [`context`][Error::context] does not change the origin, so this
+    /// is where the innermost error was turned into an `Error`. See
+    /// [`Origin::version`] for whose version is recorded.
+    ///
+    /// This method is only available if the `build-info` Cargo feature is
+    /// enabled.
+    #[cfg(feature = "build-info")]
+    pub fn origin(&self) -> Origin {
+        self.inner.origin
+    }
+
+    /// The metadata of the [`tracing`] span that was current when this error
+    /// was created, or `None` if there was no span or no subscriber.
+    ///
+    /// Adding [`context`][Error::context] does not change the span, so this
+    /// is where the innermost error was turned into an `Error`. The span is
+    /// also shown in the `Debug` representation, above the backtrace.
+    ///
+    /// Only the static metadata is kept, so recording it does not allocate or
+    /// keep the span open, but the values of the span's fields are not
+    /// available.
+    ///
+    /// This method is only available if the `tracing` Cargo feature is
+    /// enabled.
+    #[cfg(feature = "tracing")]
+    pub fn span_trace(&self) -> Option<&'static Metadata<'static>> {
+        self.inner.span
+    }
+
+    /// The process exit code to report if this error reaches the top of
+    /// `main`.
+    ///
+    /// This is 1 unless a different code was set by
+    /// [`with_exit_code`][Error::with_exit_code].
+    pub fn exit_code(&self) -> i32 {
+        self.inner.attachments().exit_code.unwrap_or(1)
+    }
+
+    /// Set the process exit code to report if this error reaches the top of
+    /// `main`.
+    ///
+    /// The exit code is only observed when `main` returns a
+    /// [`Report`][crate::Report]. The standard library's `Termination` impl
+    /// for `Result` always exits with code 1 regardless of the error. A
+    /// `Report` never exits successfully because of an error: a code that
+    /// would be reported as 0 is replaced by a generic failure.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anyhow::{anyhow, Error};
+    ///
+    /// const EX_USAGE: i32 = 64;
+    ///
+    /// let error = anyhow!("missing required argument").with_exit_code(EX_USAGE);
+    /// assert_eq!(error.exit_code(), EX_USAGE);
+    /// ```
+    pub fn with_exit_code(mut self, code: i32) -> Self {
+        self.inner.attachments_mut().exit_code = Some(code);
+        self
+    }
+
+    /// The machine-readable error code set by
+    /// [`with_code`][Error::with_code], if any.
+    ///
+    /// The code belongs to the error as a whole rather than to one link of
+    /// its chain, so it is kept when [context][Error::context] is added and
+    /// there is no need to search the chain for it. If codes were set more
+    /// than once, the most recent one is returned.
+    pub fn code(&self) -> Option<&'static str> {
+        self.inner.attachments().code
+    }
+
+    /// Attach a stable, machine-readable code to this error, for example to
+    /// include in an API response.
+    ///
+    /// Any code set previously is replaced.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anyhow::{anyhow, Context, Result};
+    ///
+    /// fn fetch() -> Result<String> {
+    ///     Err(anyhow!("no response after 30s").with_code("E_TIMEOUT"))
+    /// }
+    ///
+    /// let error = fetch().context("failed to fetch profile").unwrap_err();
+    /// assert_eq!(error.code(), Some("E_TIMEOUT"));
+    /// ```
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        self.inner.attachments_mut().code = Some(code);
+        self
+    }
+
+    /// The severity set by [`with_severity`][Error::with_severity], or
+    /// [`Severity::Error`] if none was set.
+    ///
+    /// Like the [code][Error::code], the severity belongs to the error as a
+    /// whole, so it is kept when [context][Error::context] is added. There is
+    /// only ever one severity: setting it again, whether or not context was
+    /// added in between, replaces it. So when the layers of an error are
+    /// built up from the inside out, the outermost layer to set a severity
+    /// wins.
+    pub fn severity(&self) -> Severity {
+        self.inner.attachments().severity.unwrap_or_default()
+    }
+
+    /// Set how serious this error is, for routing it in logs.
+    ///
+    /// Any severity set previously is replaced.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anyhow::{anyhow, Context, Result, Severity};
+    ///
+    /// fn load_cache() -> Result<Vec<u8>> {
+    ///     Err(anyhow!("cache is stale").with_severity(Severity::Warning))
+    /// }
+    ///
+    /// let error = load_cache().context("failed to load cache").unwrap_err();
+    /// assert_eq!(error.severity(), Severity::Warning);
+    /// ```
+    pub fn with_severity(mut self, severity: Severity) -> Self {
+        self.inner.attachments_mut().severity = Some(severity);
+        self
+    }
+
+    /// Whether this error was marked as worth retrying the failed operation
+    /// for, by [`mark_retryable`][Error::mark_retryable] or
+    /// [`Context::retryable_context`][crate::Context::retryable_context].
+    ///
+    /// Errors are not retryable unless marked. Like the
+    /// [severity][Error::severity], the flag belongs to the error as a whole:
+    /// it is kept when [context][Error::context] is added, and marking the
+    /// error again, before or after adding context, replaces it. So the
+    /// outermost layer to mark the error decides.
+    pub fn is_retryable(&self) -> bool {
+        self.inner.attachments().retryable
+    }
+
+    /// Mark this error as worth retrying the failed operation for, or not,
+    /// so that retry logic can decide without inspecting the error types.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anyhow::{anyhow, Context, Result};
+    ///
+    /// fn fetch() -> Result<Vec<u8>> {
+    ///     Err(anyhow!("connection reset").mark_retryable(true))
+    /// }
+    ///
+    /// let error = fetch().context("failed to fetch index").unwrap_err();
+    /// assert!(error.is_retryable());
+    /// ```
+    pub fn mark_retryable(mut self, retryable: bool) -> Self {
+        self.inner.attachments_mut().retryable = retryable;
+        self
+    }
+
+    /// The errors attached by [`add_related`][Error::add_related], in the
+    /// order they were attached.
+    pub fn related(&self) -> &[Error] {
+        &self.inner.attachments().related
+    }
+
+    /// The errors grouped by [`from_errors`][Error::from_errors], in order.
+    ///
+    /// Context added to the group is looked through. Any other error has no
+    /// sub-errors and returns an empty slice.
+    pub fn sub_errors(&self) -> &[Error] {
+        match self.downcast_ref_inner::<AggregateError>() {
+            Some(aggregate) => &aggregate.errors,
+            None => &[],
+        }
+    }
+
+    /// Whether this error was created by [`from_errors`][Error::from_errors],
+    /// possibly with context added since.
+    ///
+    /// This is `true` even for a group of no errors, which
+    /// [`sub_errors`][Error::sub_errors] alone does not tell apart from an
+    /// error that is not a group.
+    pub fn is_aggregate(&self) -> bool {
+        self.downcast_ref_inner::<AggregateError>().is_some()
+    }
+
+    /// Attach another error that occurred alongside this one, such as a
+    /// failure while cleaning up after it.
+    ///
+    /// The related error is not a cause of this one, so it is not part of
+    /// [`chain()`][Error::chain] and does not affect downcasting. It is kept
+    /// rather than discarded, can be read back with
+    /// [`related`][Error::related], and is rendered by the `Debug`
+    /// representation under an "Additionally:" heading after the causes, one
+    /// line per related error with the messages of its chain joined by `: `.
+    /// Like notes, related errors are kept when [context][Error::context] is
+    /// added.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anyhow::{anyhow, Result};
+    ///
+    /// fn transfer() -> Result<()> {
+    ///     # const IGNORE: &str = stringify! {
+    ///     ...
+    ///     # };
+    ///     # Err(anyhow!("connection reset"))
+    /// }
+    ///
+    /// fn rollback() -> Result<()> {
+    ///     # const IGNORE: &str = stringify! {
+    ///     ...
+    ///     # };
+    ///     # Err(anyhow!("database is locked"))
+    /// }
+    ///
+    /// fn run() -> Result<()> {
+    ///     let error = match transfer() {
+    ///         Ok(()) => return Ok(()),
+    ///         Err(error) => error,
+    ///     };
+    ///     Err(match rollback() {
+    ///         Ok(()) => error,
+    ///         Err(cleanup) => error.add_related(cleanup.context("rollback failed")),
+    ///     })
+    /// }
     /// #
-    /// # let ret =
-    /// // If the error was caused by redaction, then return a tombstone instead
-    /// // of the content.
-    /// match root_cause.downcast_ref::<DataStoreError>() {
-    ///     Some(DataStoreError::Censored(_)) => Ok(Poll::Ready(REDACTED_CONTENT)),
-    ///     None => Err(error),
+    /// # let error = run().unwrap_err();
+    /// # assert_eq!(error.related().len(), 1);
+    /// ```
+    pub fn add_related(mut self, other: Error) -> Self {
+        self.inner.attachments_mut().related.push(other);
+        self
+    }
+
+    /// The URL set by [`with_url`][Error::with_url], if any.
+    pub fn url(&self) -> Option<&str> {
+        self.inner.attachments().url.as_deref()
+    }
+
+    /// Point to documentation about this error, such as a page describing
+    /// how to resolve it.
+    ///
+    /// The URL is rendered by the `Debug` representation as a `See:` line,
+    /// or whichever [label][crate::Labels::see] is set, after the causes and
+    /// any [notes][Error::note]. Unlike notes, of which there can be any
+    /// number of free-form text, there is at most one URL and it can be read
+    /// back with [`url`][Error::url], for example to render it as a link.
+    /// Setting it again replaces it. Like the [code][Error::code], it is kept
+    /// when [context][Error::context] is added.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anyhow::{anyhow, Error};
+    ///
+    /// fn connect() -> Result<(), Error> {
+    ///     # const IGNORE: &str = stringify! {
+    ///     ...
+    ///     # };
+    ///     Err(anyhow!("certificate expired").with_url("https://example.com/docs/tls"))
     /// }
-    /// # ;
     /// ```
-    pub fn downcast_ref<E>(&self) -> Option<&E>
+    ///
+    /// ```console
+    /// Error: certificate expired
+    ///
+    /// See: https://example.com/docs/tls
+    /// ```
+    pub fn with_url<U>(mut self, url: U) -> Self
     where
-        E: Display + Debug + Send + Sync + 'static,
+        U: Into<String>,
     {
-        if self.is::<E>() {
-            unsafe { Some(&*(self.inner.error() as *const dyn StdError as *const E)) }
-        } else {
-            None
+        self.inner.attachments_mut().url = Some(url.into());
+        self
+    }
+
+    /// Wrap the error value with additional context, recording the
+    /// [`ErrorKind`][io::ErrorKind] of the `io::Error` it is wrapping.
+    ///
+    /// This is the same as [`context`][Error::context] except that the kind of
+    /// the first `io::Error` in the chain is recorded, to be read back cheaply
+    /// by [`io_kind`][Error::io_kind] however many more layers of context are
+    /// added later. If there is no `io::Error` in the chain, any kind recorded
+    /// previously is kept.
+    ///
+    /// Requires the `std` feature.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anyhow::Error;
+    /// use std::fs;
+    /// use std::io;
+    ///
+    /// fn read_config() -> anyhow::Result<String> {
+    ///     fs::read_to_string("/nonexistent/config.toml")
+    ///         .map_err(|error| Error::new(error).context_kind("failed to read config"))
+    /// }
+    ///
+    /// let error = read_config().unwrap_err();
+    /// assert_eq!(error.io_kind(), Some(io::ErrorKind::NotFound));
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn context_kind<C>(self, context: C) -> Self
+    where
+        C: Display + Send + Sync + 'static,
+    {
+        let kind = self
+            .chain_downcast::<io::Error>()
+            .next()
+            .map(io::Error::kind);
+        let mut error = self.context(context);
+        if kind.is_some() {
+            error.inner.attachments_mut().io_kind = kind;
+        }
+        error
+    }
+
+    /// The [`ErrorKind`][io::ErrorKind] recorded by
+    /// [`context_kind`][Error::context_kind], if any.
+    ///
+    /// This does not search the chain, so it is `None` unless `context_kind`
+    /// was used on this error or on an error it was built from by adding
+    /// context.
+    ///
+    /// Requires the `std` feature.
+    #[cfg(feature = "std")]
+    pub fn io_kind(&self) -> Option<io::ErrorKind> {
+        self.inner.attachments().io_kind
+    }
+
+    /// The [`ErrorKind`][io::ErrorKind] of the first `io::Error` in the chain,
+    /// if any.
+    ///
+    /// Unlike [`io_kind`][Error::io_kind], this searches the chain every time
+    /// it is called, so it finds an `io::Error` however the error was built.
+    ///
+    /// Requires the `std` feature.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anyhow::Context;
+    /// use std::fs;
+    /// use std::io;
+    ///
+    /// let error = fs::read_to_string("/nonexistent/config.toml")
+    ///     .context("failed to read config")
+    ///     .unwrap_err();
+    /// assert_eq!(error.io_error_kind(), Some(io::ErrorKind::NotFound));
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn io_error_kind(&self) -> Option<io::ErrorKind> {
+        self.chain_downcast::<io::Error>()
+            .next()
+            .map(io::Error::kind)
+    }
+
+    /// Get the backtrace for this Error.
+    ///
+    /// Backtraces are only available on the nightly channel. Tracking issue:
+    /// [rust-lang/rust#53487][tracking].
+    ///
+    /// In order for the backtrace to be meaningful, the environment variable
+    /// `RUST_LIB_BACKTRACE=1` must be defined. Backtraces are somewhat
+    /// expensive to capture in Rust, so we don't necessarily want to be
+    /// capturing them all over the place all the time.
+    ///
+    /// Most of that expense is deferred. Creating the error only walks the
+    /// stack and records the address of each frame; the addresses are
+    /// resolved to function names and source locations the first time the
+    /// backtrace is formatted or its frames are inspected, on whichever
+    /// thread does so, and the result is kept for later uses. An error that
+    /// is handled without ever printing its backtrace never pays for
+    /// resolving it.
+    ///
+    /// [tracking]: https://github.com/rust-lang/rust/issues/53487
+    #[cfg(backtrace)]
+    pub fn backtrace(&self) -> &Backtrace {
+        // NB: this unwrap can only fail if the underlying error's backtrace
+        // method is nondeterministic, which would only happen in maliciously
+        // constructed code
+        self.inner
+            .backtrace
+            .as_ref()
+            .or_else(|| error::request_ref::<Backtrace>(self.inner.error()))
+            .expect("backtrace capture failed")
+    }
+
+    /// Returns `true` if a usable backtrace was captured for this Error.
+    ///
+    /// This only checks the [status][std::backtrace::Backtrace::status] of the
+    /// backtrace, which is cheap: the backtrace is not resolved to symbols or
+    /// formatted. Whether one is captured depends on the `RUST_LIB_BACKTRACE`
+    /// and `RUST_BACKTRACE` environment variables, unless the underlying error
+    /// provides its own.
+    ///
+    /// As backtraces are only available on the nightly channel, this always
+    /// returns `false` elsewhere.
+    pub fn has_backtrace(&self) -> bool {
+        #[cfg(backtrace)]
+        return self.backtrace().status() == BacktraceStatus::Captured;
+
+        #[cfg(not(backtrace))]
+        return false;
+    }
+
+    /// An iterator of the frames of the backtrace for this Error.
+    ///
+    /// This provides the same information as [`backtrace()`][Error::backtrace]
+    /// but frame by frame, for example to filter out frames from the standard
+    /// library or to render the backtrace in a custom format. How much detail
+    /// each frame has depends on the debug info available in the binary.
+    ///
+    /// The first frame is the code that created the error: the frames of the
+    /// standard library capturing the backtrace and of anyhow constructing the
+    /// error are left out, whatever [`set_trim_backtrace`] is set to. They are
+    /// still part of the [`Backtrace`] returned by
+    /// [`backtrace()`][Error::backtrace], which cannot be edited.
+    ///
+    /// The iterator is empty if no backtrace was captured.
+    ///
+    /// Backtraces are only available on the nightly channel. Tracking issue:
+    /// [rust-lang/rust#53487][tracking].
+    ///
+    /// [tracking]: https://github.com/rust-lang/rust/issues/53487
+    /// [`set_trim_backtrace`]: crate::set_trim_backtrace
+    #[cfg(backtrace)]
+    pub fn backtrace_frames(&self) -> impl Iterator<Item = BacktraceFrame> {
+        backtrace::call_site_frames(self.backtrace()).into_iter()
+    }
+
+    /// Every distinct backtrace reachable from this Error.
+    ///
+    /// The first is the one captured by anyhow when the error was created, if
+    /// it captured one, followed by the backtraces provided through the
+    /// standard library's [`Error::provide`][std::error::Error::provide] API
+    /// by the errors in [`chain()`][Error::chain], in order. This tells apart
+    /// where an error was originally created from where it was turned into an
+    /// `Error`, which [`backtrace()`][Error::backtrace] alone does not.
+    ///
+    /// Like backtraces, this is only available on the nightly channel.
+    /// Tracking issue: [rust-lang/rust#53487][tracking].
+    ///
+    /// [tracking]: https://github.com/rust-lang/rust/issues/53487
+    #[cfg(backtrace)]
+    pub fn backtraces(&self) -> Vec<&Backtrace> {
+        let mut backtraces = Vec::new();
+        backtraces.extend(self.inner.backtrace.as_ref());
+        for link in self.chain() {
+            // Layers of context provide the backtrace of the error beneath
+            // them, so the same one is found more than once.
+            if let Some(backtrace) = error::request_ref::<Backtrace>(link) {
+                if !backtraces.iter().any(|seen| ptr::eq(*seen, backtrace)) {
+                    backtraces.push(backtrace);
+                }
+            }
         }
+        backtraces
+    }
+
+    /// Request a reference of type `T` from the errors in the chain, using the
+    /// standard library's [`Error::provide`][std::error::Error::provide] API.
+    ///
+    /// The errors are asked in order from outermost to innermost, and the
+    /// first one to provide a `T` wins. A request for a `Backtrace` is answered
+    /// with the same backtrace as [`backtrace()`][Error::backtrace].
+    ///
+    /// Like backtraces, this is only available on the nightly channel.
+    /// Tracking issue: [rust-lang/rust#99301][tracking].
+    ///
+    /// [tracking]: https://github.com/rust-lang/rust/issues/99301
+    #[cfg(backtrace)]
+    pub fn request_ref<T>(&self) -> Option<&T>
+    where
+        T: ?Sized + 'static,
+    {
+        error::request_ref::<T>(self.as_wrapper())
+            .or_else(|| self.chain().skip(1).find_map(error::request_ref::<T>))
+    }
+
+    /// Request a value of type `T` from the errors in the chain, using the
+    /// standard library's [`Error::provide`][std::error::Error::provide] API.
+    ///
+    /// The errors are asked in order from outermost to innermost, and the
+    /// first one to provide a `T` wins.
+    ///
+    /// Like backtraces, this is only available on the nightly channel.
+    /// Tracking issue: [rust-lang/rust#99301][tracking].
+    ///
+    /// [tracking]: https://github.com/rust-lang/rust/issues/99301
+    #[cfg(backtrace)]
+    pub fn request_value<T>(&self) -> Option<T>
+    where
+        T: 'static,
+    {
+        error::request_value::<T>(self.as_wrapper())
+            .or_else(|| self.chain().skip(1).find_map(error::request_value::<T>))
+    }
+
+    #[cfg(backtrace)]
+    fn as_wrapper(&self) -> &ErrorWrapper {
+        unsafe { &*(self as *const Error as *const ErrorWrapper) }
+    }
+
+    // Provides this error's backtrace followed by whatever the outermost
+    // error in the chain provides.
+    #[cfg(backtrace)]
+    pub(crate) fn provide<'a>(&'a self, request: &mut Request<'a>) {
+        request.provide_ref::<Backtrace>(self.backtrace());
+        self.inner.error().provide(request);
+    }
+
+    /// An iterator of the chain of source errors contained by this Error.
+    ///
+    /// This iterator will visit every error in the cause chain of this error
+    /// object, beginning with the error that this error object was created
+    /// from.
+    ///
+    /// If a buggy `source()` implementation leads back to an error that was
+    /// already visited, the iteration stops instead of cycling forever. Every
+    /// error of such a cycle is visited at least once, and errors of the cycle
+    /// may be visited a few times over before the cycle is detected. Detecting
+    /// cycles costs neither allocation nor additional calls to `source()`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anyhow::Error;
+    /// use std::io;
+    ///
+    /// pub fn underlying_io_error_kind(error: &Error) -> Option<io::ErrorKind> {
+    ///     for cause in error.chain() {
+    ///         if let Some(io_error) = cause.downcast_ref::<io::Error>() {
+    ///             return Some(io_error.kind());
+    ///         }
+    ///     }
+    ///     None
+    /// }
+    /// ```
+    pub fn chain(&self) -> Chain<'_> {
+        Chain {
+            sources: self.inner.attachments().sources.iter(),
+            ..Chain::new(self.inner.error())
+        }
+    }
+
+    /// An iterator of the chain of source errors contained by this Error.
+    ///
+    /// This is an alias of [`chain()`][Error::chain], under the name of the
+    /// `sources` method that the standard library's `Error` trait provides
+    /// on nightly. Like `chain()`, that method begins with the error itself.
+    pub fn sources(&self) -> Chain<'_> {
+        self.chain()
+    }
+
+    /// Convert into a reference counted `std::error::Error` trait object
+    /// that can be handed to several consumers.
+    ///
+    /// The error behind the vtable of `Error` cannot be moved out on its own
+    /// without also discarding the context, notes and sources attached to it,
+    /// so the whole `Error` is moved into the `Arc` instead. The trait object
+    /// displays as this error and its `source()` continues into this error's
+    /// chain of causes. Downcasting the trait object does not reach the
+    /// original error type; use [`downcast`][Error::downcast] beforehand if
+    /// consumers need the concrete type.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anyhow::anyhow;
+    /// use std::thread;
+    ///
+    /// let error = anyhow!("oh no!").into_arc();
+    /// let handles: Vec<_> = (0..2)
+    ///     .map(|_| {
+    ///         let error = error.clone();
+    ///         thread::spawn(move || error.to_string())
+    ///     })
+    ///     .collect();
+    /// for handle in handles {
+    ///     assert_eq!(handle.join().unwrap(), "oh no!");
+    /// }
+    /// ```
+    pub fn into_arc(self) -> Arc<dyn StdError + Send + Sync + 'static> {
+        Arc::new(ErrorWrapper(self))
+    }
+
+    /// Move the underlying error out of this error object as a boxed
+    /// `std::error::Error`.
+    ///
+    /// Unlike [`into_arc`][Error::into_arc] this does not keep the `Error`
+    /// around it: the backtrace, notes, exit code and error code are dropped.
+    /// An error created by [`from_boxed`][Error::from_boxed] returns the
+    /// original box. If context has been added, the result is the outermost
+    /// context, whose `source()` leads to the error beneath it.
+    ///
+    /// Otherwise the box holds the very error passed to [`Error::new`], not a
+    /// wrapper around it, so that downcasting the box to that error's type is
+    /// guaranteed to succeed. This does not extend to an ad-hoc error created
+    /// from a message, as the message type need not implement
+    /// `std::error::Error`; the box then holds a private type that displays
+    /// as the message.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anyhow::Error;
+    /// use std::io;
+    ///
+    /// let error = Error::new(io::Error::from(io::ErrorKind::NotFound));
+    /// let boxed = error.into_inner();
+    /// assert!(boxed.is::<io::Error>());
+    /// ```
+    pub fn into_inner(self) -> Box<dyn StdError + Send + Sync + 'static> {
+        let error = match self.downcast::<Box<dyn StdError + Send + Sync>>() {
+            Ok(boxed) => return boxed,
+            Err(error) => error,
+        };
+        unsafe {
+            let inner = ptr::read(&error.inner);
+            mem::forget(error);
+            (inner.into_boxed)(inner)
+        }
+    }
+
+    /// Convert this error object into a raw pointer, for handing it across an
+    /// FFI boundary without copying.
+    ///
+    /// The pointer is opaque and is never null. Ownership of the error moves
+    /// to the caller, who must eventually pass the pointer to
+    /// [`from_raw`][Error::from_raw] exactly once to drop it or to use it
+    /// again as an `Error`; otherwise the error is leaked.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anyhow::{anyhow, Error};
+    ///
+    /// let error = anyhow!("oh no!");
+    /// let ptr = error.into_raw();
+    /// let error = unsafe { Error::from_raw(ptr) };
+    /// assert_eq!(error.to_string(), "oh no!");
+    /// ```
+    pub fn into_raw(self) -> *mut () {
+        unsafe {
+            let inner = ptr::read(&self.inner);
+            mem::forget(self);
+            Box::into_raw(inner).cast::<()>()
+        }
+    }
+
+    /// Reconstruct an error object from a pointer returned by
+    /// [`into_raw`][Error::into_raw].
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by `Error::into_raw` of this same build
+    /// of anyhow, and must not be passed to `from_raw` more than once.
+    pub unsafe fn from_raw(ptr: *mut ()) -> Self {
+        Error {
+            inner: Box::from_raw(ptr.cast::<ErrorImpl<()>>()),
+        }
+    }
+
+    /// Render the full human-readable report: this error, the numbered chain
+    /// of causes, any notes, and the backtrace if one was captured.
+    ///
+    /// This is the same text as the `Debug` representation, including any
+    /// hook installed by [`set_hook`][crate::set_hook], but by way of
+    /// `Display` so that it can be asked for explicitly wherever `Debug`
+    /// output is suppressed or abbreviated.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anyhow::anyhow;
+    ///
+    /// let error = anyhow!("connection refused").context("failed to fetch index");
+    /// println!("{}", error.report());
+    /// ```
+    pub fn report(&self) -> impl Display + '_ {
+        ErrorReport(self)
+    }
+
+    /// An iterator of the errors in the chain paired with their depth.
+    ///
+    /// The depth is 0 for this error itself and increases by one for each
+    /// error visited by [`chain()`][Error::chain] after it, counting through
+    /// context and nested `Error`s alike.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anyhow::Error;
+    ///
+    /// fn render_tree(error: &Error) -> String {
+    ///     let mut tree = String::new();
+    ///     for (depth, cause) in error.chain_enumerated() {
+    ///         tree += &format!("{:indent$}{}\n", "", cause, indent = depth * 2);
+    ///     }
+    ///     tree
+    /// }
+    /// ```
+    pub fn chain_enumerated(&self) -> impl Iterator<Item = (usize, &(dyn StdError + 'static))> {
+        self.chain().enumerate()
+    }
+
+    /// The number of errors in the chain, including this error itself.
+    ///
+    /// This is the number of errors visited by [`chain()`][Error::chain], so
+    /// it is always at least 1.
+    pub fn chain_len(&self) -> usize {
+        self.chain().count()
+    }
+
+    /// Returns `true` if the errors in the chain display as `messages`, one
+    /// for one and in order, starting with this error itself.
+    ///
+    /// Only the rendered `Display` strings are compared, not the types of the
+    /// errors, which makes this convenient for assertions in tests.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anyhow::anyhow;
+    ///
+    /// let error = anyhow!("disk full").context("failed to save");
+    /// assert!(error.chain_eq(&["failed to save", "disk full"]));
+    /// ```
+    pub fn chain_eq(&self, messages: &[&str]) -> bool {
+        let mut chain = self.chain();
+        let all_equal = messages.iter().all(|message| match chain.next() {
+            Some(error) => error.to_string() == *message,
+            None => false,
+        });
+        all_equal && chain.next().is_none()
+    }
+
+    /// The `Display` strings of the errors in the chain, starting with the
+    /// root cause.
+    ///
+    /// This is the reverse of the order of [`chain()`][Error::chain]: the
+    /// first string is that of the [`root_cause()`][Error::root_cause] and the
+    /// last is that of this error itself, for logs that put the root cause
+    /// first.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anyhow::anyhow;
+    ///
+    /// let error = anyhow!("disk full").context("failed to save");
+    /// assert_eq!(error.chain_strings_rev(), ["disk full", "failed to save"]);
+    /// ```
+    pub fn chain_strings_rev(&self) -> Vec<String> {
+        let mut strings: Vec<String> = self.chain().map(|error| error.to_string()).collect();
+        strings.reverse();
+        strings
+    }
+
+    /// Consume this error, returning the `Display` strings of the errors in
+    /// the chain, starting with this error itself.
+    ///
+    /// The strings are in the order of [`chain()`][Error::chain]. The error,
+    /// including its backtrace, is dropped before this returns, so that only
+    /// the summary is held on to, for example to send it to another thread.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anyhow::anyhow;
+    ///
+    /// let error = anyhow!("disk full").context("failed to save");
+    /// assert_eq!(error.into_chain_strings(), ["failed to save", "disk full"]);
+    /// ```
+    pub fn into_chain_strings(self) -> Vec<String> {
+        self.chain().map(|error| error.to_string()).collect()
+    }
+
+    /// A new error whose chain holds the messages of this one, each
+    /// transformed by `f`.
+    ///
+    /// `f` is called with the `Display` string of each error in
+    /// [`chain()`][Error::chain], in order, and the chain of the new error
+    /// consists of the strings it returns. This error is left as it was. This
+    /// is for sanitizing or localizing the messages of an error before
+    /// showing it to someone.
+    ///
+    /// Only the messages are carried over. The new error cannot be downcast
+    /// to the types of the original errors, and its backtrace is captured
+    /// anew rather than copied; notes, codes and other attachments are not
+    /// copied either.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anyhow::anyhow;
+    ///
+    /// let error = anyhow!("password hunter2 rejected").context("failed to log in");
+    /// let redacted = error.map_messages(|message| message.replace("hunter2", "***"));
+    /// assert!(redacted.chain_eq(&["failed to log in", "password *** rejected"]));
+    /// ```
+    pub fn map_messages<F>(&self, f: F) -> Error
+    where
+        F: Fn(&str) -> String,
+    {
+        let messages = self.chain().map(|error| f(&error.to_string())).collect();
+        Error::new(LocalError::from_messages(messages))
+    }
+
+    /// A hash of the messages in the chain, for grouping occurrences of the
+    /// same logical error.
+    ///
+    /// The `Display` strings of the errors visited by
+    /// [`chain()`][Error::chain] are hashed in order with a fixed algorithm
+    /// (64-bit FNV-1a), so identical chains produce the same value across
+    /// runs, processes and builds. Nothing else goes into the hash: the
+    /// backtrace, the creation timestamp, notes, codes and the types of the
+    /// errors are all ignored.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anyhow::anyhow;
+    ///
+    /// let a = anyhow!("disk full").context("failed to save");
+    /// let b = anyhow!("disk full").context("failed to save");
+    /// assert_eq!(a.fingerprint(), b.fingerprint());
+    /// ```
+    pub fn fingerprint(&self) -> u64 {
+        let mut hasher = Fingerprint::new();
+        for error in self.chain() {
+            let _ = fmt::write(&mut hasher, format_args!("{}", error));
+            // Separate the messages so that ["ab", "c"] and ["a", "bc"] do
+            // not collide. No UTF-8 string contains the byte 0xFF.
+            hasher.write_bytes(&[0xFF]);
+        }
+        hasher.0
+    }
+
+    // The name of the type of a link of the chain, for those that anyhow
+    // knows the type of: the layers of this error, and the error wrapped by a
+    // layer of context that is not itself an Error. For a layer of context
+    // this is the type of the context.
+    fn link_type_name(&self, link: &(dyn StdError + 'static)) -> Option<&'static str> {
+        let mut error = self;
+        loop {
+            let layer = error.inner.error();
+            if same_error(layer, link) {
+                return Some(error.inner.type_name);
+            }
+            let context = error.inner.context?;
+            error = match (context.inner_error)(layer) {
+                Some(inner) => inner,
+                None => {
+                    let inner = layer.source()?;
+                    return same_error(inner, link).then_some(context.inner_type_name);
+                }
+            };
+        }
+    }
+
+    /// Returns `true` if `link` is one of the layers of context of this error,
+    /// as opposed to an underlying error.
+    ///
+    /// The layers of context are those added by [`context`][Error::context]
+    /// or [`Context`][crate::Context]. `link` is compared by identity, so it
+    /// should be obtained from [`chain()`][Error::chain] of this same error.
+    /// Links of any other error, and errors added by
+    /// [`wrap_source`][Error::wrap_source], are never reported as context.
+    ///
+    /// This is a stable part of the API for rendering context differently from
+    /// the errors it describes. How context is represented internally is not,
+    /// so it should not be recognized by any other means, such as by its type
+    /// name.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anyhow::Error;
+    ///
+    /// fn render(error: &Error) -> String {
+    ///     let mut rendered = String::new();
+    ///     for link in error.chain() {
+    ///         if error.is_context_frame(link) {
+    ///             rendered += &format!("while {}:\n", link);
+    ///         } else {
+    ///             rendered += &format!("error: {}\n", link);
+    ///         }
+    ///     }
+    ///     rendered
+    /// }
+    /// ```
+    pub fn is_context_frame(&self, link: &(dyn StdError + 'static)) -> bool {
+        // A layer of context can start at the same address as the error it
+        // wraps, so the vtable is compared as well.
+        let link = unsafe { mem::transmute::<&dyn StdError, TraitObject>(link) };
+        let mut error = self;
+        while let Some(context) = error.inner.context {
+            let layer = error.inner.error();
+            let data = layer as *const dyn StdError as *const ();
+            if data == link.data && error.inner.vtable == link.vtable {
+                return true;
+            }
+            error = match (context.inner_error)(layer) {
+                Some(inner) => inner,
+                None => return false,
+            };
+        }
+        false
+    }
+
+    /// The source location recorded for `link` by
+    /// [`context_at`][Error::context_at], if any.
+    ///
+    /// `link` is compared by identity, as by
+    /// [`is_context_frame`][Error::is_context_frame], so it should be obtained
+    /// from [`chain()`][Error::chain] of this same error. Each layer of
+    /// context has its own location: adding more context does not change the
+    /// location of the layers beneath. Links without a recorded location,
+    /// including every link that is not a layer of context, return `None`.
+    pub fn link_location(
+        &self,
+        link: &(dyn StdError + 'static),
+    ) -> Option<&'static Location<'static>> {
+        let mut error = self;
+        loop {
+            let layer = error.inner.error();
+            if same_error(layer, link) {
+                return error.inner.location;
+            }
+            let context = error.inner.context?;
+            error = (context.inner_error)(layer)?;
+        }
+    }
+
+    /// An iterator of every error in the chain that is of type `E`.
+    ///
+    /// The errors are visited in the same order as [`chain()`][Error::chain],
+    /// from the outermost to the root cause.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anyhow::Error;
+    /// use std::io;
+    ///
+    /// pub fn io_error_kinds(error: &Error) -> Vec<io::ErrorKind> {
+    ///     error.chain_downcast::<io::Error>().map(io::Error::kind).collect()
+    /// }
+    /// ```
+    pub fn chain_downcast<E>(&self) -> impl Iterator<Item = &E>
+    where
+        E: StdError + 'static,
+    {
+        self.chain().filter_map(|cause| cause.downcast_ref::<E>())
+    }
+
+    /// The first error in the chain that is of type `E`, with its depth.
+    ///
+    /// The depth is counted as by [`chain_enumerated`][Error::chain_enumerated]:
+    /// 0 for this error itself, and one more for each error after it in
+    /// [`chain()`][Error::chain]. A small depth suggests an error close to
+    /// what the user sees, a large one an implementation detail.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anyhow::Context;
+    /// use std::num::ParseIntError;
+    ///
+    /// let error = "http".parse::<u16>().context("invalid port").unwrap_err();
+    /// let (depth, _) = error.find_downcast_ref::<ParseIntError>().unwrap();
+    /// assert_eq!(depth, 1);
+    /// ```
+    pub fn find_downcast_ref<E>(&self) -> Option<(usize, &E)>
+    where
+        E: StdError + 'static,
+    {
+        self.chain_enumerated()
+            .find_map(|(depth, cause)| Some((depth, cause.downcast_ref::<E>()?)))
+    }
+
+    /// Apply `f` to each error in the chain and return the first result that
+    /// is not `None`.
+    ///
+    /// The errors are visited in the same order as [`chain()`][Error::chain].
+    /// Compared to [`chain_downcast`][Error::chain_downcast], this can match
+    /// on more than the type of the error, or on one of several types.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anyhow::Error;
+    /// use std::io;
+    ///
+    /// enum Status {
+    ///     NotFound,
+    ///     BadRequest,
+    /// }
+    ///
+    /// fn status(error: &Error) -> Option<Status> {
+    ///     error.find_any(|cause| {
+    ///         if let Some(cause) = cause.downcast_ref::<io::Error>() {
+    ///             if cause.kind() == io::ErrorKind::NotFound {
+    ///                 return Some(Status::NotFound);
+    ///             }
+    ///         }
+    ///         if cause.is::<std::num::ParseIntError>() {
+    ///             return Some(Status::BadRequest);
+    ///         }
+    ///         None
+    ///     })
+    /// }
+    /// ```
+    pub fn find_any<F, R>(&self, f: F) -> Option<R>
+    where
+        F: FnMut(&(dyn StdError + 'static)) -> Option<R>,
+    {
+        self.chain().find_map(f)
+    }
+
+    /// The lower-level source of this error, if any.
+    ///
+    /// This is the second error in the iterator produced by
+    /// [`chain()`][Error::chain], and mirrors the `source` method of
+    /// `std::error::Error`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anyhow::{Context, Result};
+    ///
+    /// fn read_config() -> Result<String> {
+    ///     std::fs::read_to_string("/nonexistent/config.toml")
+    ///         .context("failed to read config")
+    /// }
+    ///
+    /// let error = read_config().unwrap_err();
+    /// assert!(error.source().unwrap().is::<std::io::Error>());
+    /// ```
+    pub fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        self.chain().nth(1)
+    }
+
+    /// An iterator of mutable references to the errors in the chain.
+    ///
+    /// The standard library's `source()` method only hands out shared
+    /// references, so this iterator can only reach the errors that are owned by
+    /// this `Error` itself. Those are:
+    ///
+    /// - the error this error object was created from, or in the case of
+    ///   errors wrapped by [`context`][Error::context] the error beneath
+    ///   every layer of context;
+    /// - every source added by [`wrap_source`][Error::wrap_source].
+    ///
+    /// Layers of context are skipped, as are the sources of any of the above
+    /// errors. Compared to [`chain()`][Error::chain], the errors that are
+    /// visited appear in the same relative order.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::fmt::{self, Display};
+    /// #
+    /// # #[derive(Debug)]
+    /// # struct LocalizedError {
+    /// #     message: String,
+    /// # }
+    /// #
+    /// # impl Display for LocalizedError {
+    /// #     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    /// #         f.write_str(&self.message)
+    /// #     }
+    /// # }
+    /// #
+    /// # impl std::error::Error for LocalizedError {}
+    /// #
+    /// # fn translate(message: &str) -> String {
+    /// #     message.to_owned()
+    /// # }
+    /// #
+    /// use anyhow::Error;
+    ///
+    /// fn localize(error: &mut Error) {
+    ///     for cause in error.chain_mut() {
+    ///         if let Some(cause) = cause.downcast_mut::<LocalizedError>() {
+    ///             cause.message = translate(&cause.message);
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn chain_mut(&mut self) -> ChainMut<'_> {
+        self.inner.root_cause.clear();
+        let inner: *mut ErrorImpl<()> = &mut *self.inner;
+        unsafe {
+            // Borrow the fields separately so that the error and the appended
+            // sources can be handed out at the same time.
+            let object = TraitObject {
+                data: &mut (*inner).error as *mut () as *const (),
+                vtable: (*inner).vtable,
+            };
+            let error =
+                mem::transmute::<TraitObject, &mut (dyn StdError + Send + Sync + 'static)>(object);
+            ChainMut {
+                next: Some(LinkMut {
+                    error,
+                    type_id: (*inner).type_id,
+                    context: (*inner).context,
+                }),
+                sources: match &mut (*inner).attachments {
+                    Some(attachments) => attachments.sources.iter_mut(),
+                    None => [].iter_mut(),
+                },
+            }
+        }
+    }
+
+    /// The lowest level cause of this error &mdash; this error's cause's
+    /// cause's cause etc.
+    ///
+    /// The root cause is the last error in the iterator produced by
+    /// [`chain()`][Error::chain].
+    ///
+    /// The chain is walked on the first call only. The root cause found is
+    /// remembered, so that later calls take constant time however long the
+    /// chain is. Anything that may change the chain forgets it again, namely
+    /// [`wrap_source`][Error::wrap_source], [`chain_mut`][Error::chain_mut],
+    /// [`root_cause_mut`][Error::root_cause_mut],
+    /// [`downcast_mut`][Error::downcast_mut] and mutable dereferencing. An
+    /// error whose `source()` changes through interior mutability is not
+    /// noticed, and keeps reporting the root cause found first.
+    pub fn root_cause(&self) -> &(dyn StdError + 'static) {
+        if let Some(root_cause) = self.inner.root_cause.get() {
+            return unsafe { &*root_cause };
+        }
+        let mut chain = self.chain();
+        let mut root_cause = chain.next().unwrap();
+        for cause in chain {
+            root_cause = cause;
+        }
+        self.inner.root_cause.set(root_cause);
+        root_cause
+    }
+
+    /// The underlying error beneath any layers of context.
+    ///
+    /// This is the first error in [`chain()`][Error::chain] that is not a
+    /// layer of context added by [`context`][Error::context] or
+    /// [`Context`][crate::Context], as determined by
+    /// [`is_context_frame`][Error::is_context_frame]. Unlike
+    /// [`root_cause()`][Error::root_cause], it stops there rather than
+    /// following that error's own sources to the bottom of the chain. If this
+    /// error has no context, it is the error itself.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anyhow::Context;
+    /// use std::fs;
+    /// use std::io;
+    ///
+    /// let error = fs::read_to_string("/nonexistent/config.toml")
+    ///     .context("failed to read config")
+    ///     .unwrap_err();
+    ///
+    /// assert!(error.base().is::<io::Error>());
+    /// ```
+    pub fn base(&self) -> &(dyn StdError + 'static) {
+        // The innermost link is never context, as context always wraps
+        // another error.
+        self.chain()
+            .find(|link| !self.is_context_frame(*link))
+            .unwrap_or_else(|| self.root_cause())
+    }
+
+    /// An iterator of the errors in [`chain()`][Error::chain] that are not
+    /// layers of context.
+    ///
+    /// The layers of context added by [`context`][Error::context] or
+    /// [`Context`][crate::Context] are skipped, leaving the underlying errors
+    /// from [`base()`][Error::base] down to the root cause. Context is
+    /// recognized by [`is_context_frame`][Error::is_context_frame], which
+    /// compares each link by identity with the layers this `Error` holds
+    /// rather than by type, as there is no single type for context. Context
+    /// inside another `Error` that is the source of one of these errors
+    /// belongs to that other `Error`, so it is not recognized and is yielded
+    /// like any other error.
+    pub fn real_causes(&self) -> impl Iterator<Item = &(dyn StdError + 'static)> {
+        self.chain()
+            .filter(move |link| !self.is_context_frame(*link))
+    }
+
+    /// The zero-based index of the [root cause][Error::root_cause] within
+    /// [`chain()`][Error::chain].
+    ///
+    /// This is 0 if the error has no source, in which case the root cause is
+    /// the error itself.
+    pub fn root_cause_depth(&self) -> usize {
+        self.chain_len() - 1
+    }
+
+    /// A mutable reference to the lowest level cause of this error that can
+    /// be reached mutably.
+    ///
+    /// This is the last error visited by [`chain_mut()`][Error::chain_mut].
+    /// As the standard library's `source()` only hands out shared references,
+    /// this is the same error as [`root_cause()`][Error::root_cause] only if
+    /// that error is owned by this `Error`. If the deepest owned error has
+    /// sources of its own, they cannot be reached and that error is returned
+    /// instead.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anyhow::{Context, Result};
+    /// use std::io;
+    ///
+    /// fn read_config() -> Result<String> {
+    ///     # const IGNORE: &str = stringify! {
+    ///     ...
+    ///     # };
+    ///     # Err(io::Error::new(io::ErrorKind::NotFound, "config.toml")).context("failed to read config")
+    /// }
+    ///
+    /// fn main() {
+    ///     let mut error = read_config().unwrap_err();
+    ///     if let Some(io_error) = error.root_cause_mut().downcast_mut::<io::Error>() {
+    ///         *io_error = io::Error::new(io_error.kind(), "config.toml is missing");
+    ///     }
+    ///     assert_eq!(error.root_cause().to_string(), "config.toml is missing");
+    /// }
+    /// ```
+    pub fn root_cause_mut(&mut self) -> &mut (dyn StdError + 'static) {
+        self.chain_mut().last().unwrap()
+    }
+
+    /// Returns `true` if `E` is the type wrapped by this error object.
+    ///
+    /// This and the downcast methods only compare the `TypeId` of `E`, so `E`
+    /// is not required to implement `Display` or `Debug`.
+    pub fn is<E>(&self) -> bool
+    where
+        E: Send + Sync + 'static,
+    {
+        TypeId::of::<E>() == self.inner.type_id
+    }
+
+    /// Returns `true` if this error was created from a message rather than
+    /// from a type implementing `std::error::Error`.
+    ///
+    /// That is the case for errors created by [`anyhow!`][crate::anyhow],
+    /// [`bail!`][crate::bail], [`Error::msg`] and similar constructors taking
+    /// something that is only `Display`. As with [`is`][Error::is], only the
+    /// outermost error is looked at, so once [context][Error::context] is
+    /// added this returns `false`, whatever the error beneath the context
+    /// was created from.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anyhow::{anyhow, Error};
+    /// use std::io;
+    ///
+    /// assert!(anyhow!("oh no!").is_message());
+    /// assert!(!Error::new(io::Error::from(io::ErrorKind::NotFound)).is_message());
+    /// ```
+    pub fn is_message(&self) -> bool {
+        self.inner.message
+    }
+
+    /// Attempt to downcast the error object to a concrete type.
+    pub fn downcast<E>(self) -> Result<E, Self>
+    where
+        E: Send + Sync + 'static,
+    {
+        if let Some(error) = self.downcast_ref::<E>() {
+            // The error is moved out by reading it, after which the ErrorImpl
+            // is dropped as an ErrorImpl<()> to drop everything but the error,
+            // and Error's own Drop impl is skipped as it would drop the error
+            // a second time. None of this depends on the traits E implements.
+            unsafe {
+                let error = ptr::read(error);
+                drop(ptr::read(&self.inner));
+                mem::forget(self);
+                Ok(error)
+            }
+        } else {
+            Err(self)
+        }
+    }
+
+    /// Attempt to downcast the error object to a concrete type, adding
+    /// context to the error if it is of some other type.
+    ///
+    /// This is the same as [`downcast`][Error::downcast] followed by
+    /// [`context`][Error::context] on failure, for when an error of any other
+    /// type is to be propagated with an explanation.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anyhow::{anyhow, Result};
+    /// use std::io;
+    ///
+    /// fn into_io_error(error: anyhow::Error) -> Result<io::Error> {
+    ///     error.downcast_or_context("expected an I/O error")
+    /// }
+    ///
+    /// let error = into_io_error(anyhow!("oh no!")).unwrap_err();
+    /// assert_eq!(error.to_string(), "expected an I/O error");
+    /// ```
+    pub fn downcast_or_context<E, C>(self, context: C) -> Result<E, Self>
+    where
+        E: Send + Sync + 'static,
+        C: Display + Send + Sync + 'static,
+    {
+        self.downcast().map_err(|error| error.context(context))
+    }
+
+    /// Attempt to downcast the error object to a concrete type, looking
+    /// beneath any layers of context.
+    ///
+    /// If the error that the layers of [`context`][Error::context] were added
+    /// on top of is of type `E`, the layers are removed and their messages are
+    /// returned, outermost first, together with the underlying error.
+    /// Otherwise the error object is returned unchanged.
+    ///
+    /// Notes, exit code and sources added using
+    /// [`wrap_source`][Error::wrap_source] are discarded on success.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anyhow::{Context, Result};
+    /// use std::num::ParseIntError;
+    ///
+    /// fn parse_port(s: &str) -> Result<u16> {
+    ///     s.parse::<u16>().context("invalid port number")
+    /// }
+    ///
+    /// let error = parse_port("http").unwrap_err();
+    /// let (contexts, error) = error.downcast_with_context::<ParseIntError>().unwrap();
+    /// assert_eq!(contexts, ["invalid port number"]);
+    /// assert_eq!(error.to_string(), "invalid digit found in string");
+    /// ```
+    pub fn downcast_with_context<E>(mut self) -> Result<(Vec<String>, E), Self>
+    where
+        E: Send + Sync + 'static,
+    {
+        let mut link = self.inner.link_mut();
+        while let Some(context) = link.context {
+            link = match (context.source_mut)(link.error) {
+                Some(link) => link,
+                None => break,
+            };
+        }
+        if link.type_id != TypeId::of::<E>() {
+            return Err(self);
+        }
+
+        let mut contexts = Vec::new();
+        let mut error = self;
+        while let Some(context) = error.inner.context {
+            unsafe {
+                let inner = ptr::read(&error.inner);
+                mem::forget(error);
+                let (message, source) = (context.into_source)(inner);
+                contexts.push(message);
+                error = source;
+            }
+        }
+        match error.downcast() {
+            Ok(error) => Ok((contexts, error)),
+            Err(_) => unreachable!(),
+        }
+    }
+
+    /// Downcast this error object by reference.
+    ///
+    /// Only the outermost error is considered. If [`context`][Error::context]
+    /// was added, that is the layer of context, which never matches, even if
+    /// the error beneath it is an `E`. Use
+    /// [`downcast_ref_inner`][Error::downcast_ref_inner] to look beneath the
+    /// context, or [`chain_downcast`][Error::chain_downcast] to look at every
+    /// error in the chain.
+    ///
+    /// Being an inherent method, this takes precedence over the
+    /// `downcast_ref` method of `dyn std::error::Error`, which `Error`
+    /// dereferences to. Call that one explicitly as `(*error).downcast_ref()`
+    /// if needed; it does not match ad-hoc messages by the type of the
+    /// message.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use anyhow::anyhow;
+    /// # use std::fmt::{self, Display};
+    /// # use std::task::Poll;
+    /// #
+    /// # #[derive(Debug)]
+    /// # enum DataStoreError {
+    /// #     Censored(()),
+    /// # }
+    /// #
+    /// # impl Display for DataStoreError {
+    /// #     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+    /// #         unimplemented!()
+    /// #     }
+    /// # }
+    /// #
+    /// # impl std::error::Error for DataStoreError {}
+    /// #
+    /// # const REDACTED_CONTENT: () = ();
+    /// #
+    /// # let error = anyhow!("...");
+    /// # let root_cause = &error;
+    /// #
+    /// # let ret =
+    /// // If the error was caused by redaction, then return a tombstone instead
+    /// // of the content.
+    /// match root_cause.downcast_ref::<DataStoreError>() {
+    ///     Some(DataStoreError::Censored(_)) => Ok(Poll::Ready(REDACTED_CONTENT)),
+    ///     None => Err(error),
+    /// }
+    /// # ;
+    /// ```
+    pub fn downcast_ref<E>(&self) -> Option<&E>
+    where
+        E: Send + Sync + 'static,
+    {
+        // The type_id is that of the error behind the vtable, or that of M for
+        // a MessageError<M> which is repr(transparent). If it matches, the
+        // error is an E, whichever traits E implements.
+        if self.is::<E>() {
+            unsafe { Some(&*(self.inner.error() as *const dyn StdError as *const E)) }
+        } else {
+            None
+        }
+    }
+
+    /// Downcast the error beneath any layers of context by reference.
+    ///
+    /// This is [`downcast_ref`][Error::downcast_ref] applied to the error
+    /// that the layers of [`context`][Error::context] were added on top of,
+    /// the same error that [`base()`][Error::base] returns. If this error has
+    /// no context, it is the same as `downcast_ref`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anyhow::Context;
+    /// use std::num::ParseIntError;
+    ///
+    /// let error = "http".parse::<u16>().context("invalid port").unwrap_err();
+    /// assert!(error.downcast_ref::<ParseIntError>().is_none());
+    /// assert!(error.downcast_ref_inner::<ParseIntError>().is_some());
+    /// ```
+    pub fn downcast_ref_inner<E>(&self) -> Option<&E>
+    where
+        E: Send + Sync + 'static,
+    {
+        let mut error = self;
+        while let Some(context) = error.inner.context {
+            let layer = error.inner.error();
+            error = match (context.inner_error)(layer) {
+                Some(inner) => inner,
+                None => {
+                    if context.inner_type_id != TypeId::of::<E>() {
+                        return None;
+                    }
+                    // The source of a ContextError is the error it wraps.
+                    let inner = layer.source()?;
+                    return unsafe { Some(&*(inner as *const dyn StdError as *const E)) };
+                }
+            };
+        }
+        error.downcast_ref()
+    }
+
+    /// Alias of [`downcast_ref_inner`][Error::downcast_ref_inner], for a
+    /// downcast that sees through the layers of context.
+    ///
+    /// Each layer of [`context`][Error::context] is peeled off and the
+    /// downcast is attempted on the first link of the chain that is not
+    /// context. [`downcast_ref`][Error::downcast_ref] remains the strict form,
+    /// matching only the outermost error.
+    pub fn downcast_ref_transparent<E>(&self) -> Option<&E>
+    where
+        E: Send + Sync + 'static,
+    {
+        self.downcast_ref_inner()
+    }
+
+    /// Downcast the context value of the outermost layer of context by
+    /// reference.
+    ///
+    /// A value attached with [`context`][Error::context] or
+    /// [`Context::context`][crate::Context::context] is kept as is, so it can
+    /// be typed data rather than only a message. [`downcast_ref`] does not
+    /// match it, as it looks at the error the context was added to. This
+    /// method looks at the context instead, and returns `None` if this error
+    /// has no context or its outermost context is of another type.
+    ///
+    /// [`downcast_ref`]: Error::downcast_ref
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anyhow::anyhow;
+    /// use std::fmt::{self, Display};
+    ///
+    /// #[derive(Debug, PartialEq)]
+    /// struct RequestId(u64);
+    ///
+    /// impl Display for RequestId {
+    ///     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    ///         write!(f, "while handling request {}", self.0)
+    ///     }
+    /// }
+    ///
+    /// let error = anyhow!("connection reset").context(RequestId(42));
+    /// assert_eq!(error.context_downcast_ref::<RequestId>(), Some(&RequestId(42)));
+    /// ```
+    pub fn context_downcast_ref<C>(&self) -> Option<&C>
+    where
+        C: 'static,
+    {
+        let context = self.inner.context?;
+        (context.context_value)(self.inner.error())?.downcast_ref::<C>()
+    }
+
+    /// Downcast this error object by mutable reference.
+    pub fn downcast_mut<E>(&mut self) -> Option<&mut E>
+    where
+        E: Send + Sync + 'static,
+    {
+        if self.is::<E>() {
+            unsafe { Some(&mut *(self.inner.error_mut() as *mut dyn StdError as *mut E)) }
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(not(backtrace))]
+pub(crate) enum Backtrace {}
+
+impl<E> From<E> for Error
+where
+    E: StdError + Send + Sync + 'static,
+{
+    fn from(error: E) -> Self {
+        Error::new(error)
+    }
+}
+
+/// Convert to an `io::Error` for returning from `io::Read`, `io::Write` and
+/// similar trait impls.
+///
+/// If there is an `io::Error` anywhere in the chain, the first one's
+/// [`kind()`][io::Error::kind] is reused. Otherwise the kind is
+/// `ErrorKind::Other`. The resulting `io::Error` displays as this error and
+/// its `source()` continues into this error's chain of causes.
+///
+/// Requires the `std` feature.
+#[cfg(feature = "std")]
+impl From<Error> for io::Error {
+    fn from(error: Error) -> Self {
+        let kind = error
+            .chain()
+            .filter_map(|cause| cause.downcast_ref::<io::Error>())
+            .map(io::Error::kind)
+            .next()
+            .unwrap_or(io::ErrorKind::Other);
+        io::Error::new(kind, ErrorWrapper(error))
+    }
+}
+
+/// Convert to a boxed `std::error::Error`, as by
+/// [`into_inner`][Error::into_inner].
+///
+/// The box holds the error this `Error` was created from, so it can be
+/// downcast to that error's type. This lets `?` pass an `Error` on from a
+/// function returning `Result<T, Box<dyn std::error::Error + Send + Sync>>`.
+///
+/// No layer is added around the error: the box displays exactly as the
+/// `Error` did, and its `source()` leads through the same chain of causes,
+/// minus the errors attached by [`wrap_source`][Error::wrap_source] which are
+/// held by the `Error` rather than by any error in the chain. The error is
+/// moved into a new allocation unless it was created by
+/// [`from_boxed`][Error::from_boxed], whose box is returned as is.
+///
+/// ```
+/// use anyhow::Context;
+/// use std::error::Error as StdError;
+///
+/// fn parse(s: &str) -> anyhow::Result<u16> {
+///     s.parse::<u16>().context("invalid port")
+/// }
+///
+/// fn port(s: &str) -> Result<u16, Box<dyn StdError + Send + Sync>> {
+///     Ok(parse(s)?)
+/// }
+///
+/// let error = port("http").unwrap_err();
+/// assert_eq!(error.to_string(), "invalid port");
+/// ```
+impl From<Error> for Box<dyn StdError + Send + Sync + 'static> {
+    fn from(error: Error) -> Self {
+        error.into_inner()
+    }
+}
+
+/// Convert to a boxed `std::error::Error`, as by
+/// [`into_inner`][Error::into_inner].
+impl From<Error> for Box<dyn StdError + Send + 'static> {
+    fn from(error: Error) -> Self {
+        error.into_inner()
+    }
+}
+
+/// Convert to a boxed `std::error::Error`, as by
+/// [`into_inner`][Error::into_inner].
+impl From<Error> for Box<dyn StdError + 'static> {
+    fn from(error: Error) -> Self {
+        error.into_inner()
+    }
+}
+
+impl Deref for Error {
+    type Target = dyn StdError + Send + Sync + 'static;
+
+    fn deref(&self) -> &Self::Target {
+        self.inner.error()
+    }
+}
+
+impl DerefMut for Error {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.inner.error_mut()
+    }
+}
+
+/// Renders the error followed by its causes, notes and backtrace.
+///
+/// The causes and the frames of the backtrace are indented by four spaces,
+/// or by the width if one is given, as in `{:8?}`.
+impl Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        #[cfg(feature = "std")]
+        {
+            let hook = HOOK.read().unwrap_or_else(PoisonError::into_inner).clone();
+            if let Some(hook) = hook {
+                return hook(self, f);
+            }
+        }
+
+        self.debug_default(f)
+    }
+}
+
+impl Error {
+    // The Debug representation used when no hook is installed by set_hook.
+    pub(crate) fn debug_default(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        #[cfg(feature = "std")]
+        let labels = *LABELS.read().unwrap_or_else(PoisonError::into_inner);
+
+        #[cfg(not(feature = "std"))]
+        let labels = Labels::ENGLISH;
+
+        let show_types = SHOW_TYPES.load(Ordering::Relaxed);
+        let backtrace_hint = BACKTRACE_HINT.load(Ordering::Relaxed);
+        self.debug_with_labels(f, &labels, show_types, backtrace_hint)
+    }
+
+    #[cfg_attr(not(backtrace), allow(unused_variables))]
+    fn debug_with_labels(
+        &self,
+        f: &mut fmt::Formatter,
+        labels: &Labels,
+        show_types: bool,
+        backtrace_hint: bool,
+    ) -> fmt::Result {
+        let indent = f.width().unwrap_or(4);
+        let type_of =
+            |link| TypeAnnotation(show_types.then(|| self.link_type_name(link)).flatten());
+        let error = self.inner.error();
+        writeln!(f, "{}{}", type_of(error), error)?;
+
+        let mut chain = self.chain().skip(1).enumerate().peekable();
+        if let Some((n, error)) = chain.next() {
+            write!(f, "\n{}\n{:indent$}", labels.caused_by, "", indent = indent)?;
+            if chain.peek().is_some() {
+                write!(f, "{}: ", n)?;
+            }
+            writeln!(f, "{}{}", type_of(error), error)?;
+            for (n, error) in chain {
+                let annotation = type_of(error);
+                writeln!(
+                    f,
+                    "{:indent$}{}: {}{}",
+                    "",
+                    n,
+                    annotation,
+                    error,
+                    indent = indent
+                )?;
+            }
+        }
+
+        let sub_errors = self.sub_errors();
+        if !sub_errors.is_empty() {
+            writeln!(f, "\n{}", labels.errors)?;
+            for (n, error) in sub_errors.iter().enumerate() {
+                let line = ChainLine(error);
+                writeln!(f, "{:indent$}{}: {}", "", n, line, indent = indent)?;
+            }
+        }
+
+        let attachments = self.inner.attachments();
+        if !attachments.related.is_empty() {
+            writeln!(f, "\n{}", labels.additionally)?;
+            for related in &attachments.related {
+                let line = ChainLine(related);
+                writeln!(f, "{:indent$}{}", "", line, indent = indent)?;
+            }
+        }
+
+        if !attachments.notes.is_empty() {
+            writeln!(f)?;
+            for note in &attachments.notes {
+                write!(f, "{} ", labels.note)?;
+                // The lines of a note after the first are indented like the
+                // causes.
+                let mut indented = Indented { f, indent };
+                fmt::Write::write_fmt(&mut indented, format_args!("{}", note))?;
+                writeln!(f)?;
+            }
+        }
+
+        if let Some(url) = &attachments.url {
+            writeln!(f, "\n{} {}", labels.see, url)?;
+        }
+
+        #[cfg(feature = "tracing")]
+        {
+            if let Some(span) = self.inner.span {
+                write!(
+                    f,
+                    "\n{}\n{:indent$}{}::{}\n",
+                    labels.span_trace,
+                    "",
+                    span.target(),
+                    span.name(),
+                    indent = indent,
+                )?;
+                if let (Some(file), Some(line)) = (span.file(), span.line()) {
+                    let indent = 2 * indent;
+                    writeln!(f, "{:indent$}at {}:{}", "", file, line, indent = indent)?;
+                }
+            }
+        }
+
+        #[cfg(backtrace)]
+        {
+            let backtrace = self.backtrace();
+            match backtrace.status() {
+                BacktraceStatus::Captured => {
+                    if !RESOLVE_BACKTRACE.load(Ordering::Relaxed) {
+                        writeln!(f, "\n{}", labels.backtrace_unresolved)?;
+                    } else {
+                        let limit = BACKTRACE_FRAME_LIMIT.load(Ordering::Relaxed);
+                        let trim = TRIM_BACKTRACE.load(Ordering::Relaxed);
+                        writeln!(f)?;
+                        backtrace::write_frames(f, backtrace, limit, trim, indent)?;
+                    }
+                }
+                BacktraceStatus::Disabled if backtrace_hint => {
+                    writeln!(f, "\n{}", labels.backtrace_disabled)?;
+                }
+                _ => {}
+            }
+        }
+
+        #[cfg(all(feature = "std", not(backtrace)))]
+        {
+            if backtrace_requested(env::var_os("RUST_LIB_BACKTRACE")) {
+                writeln!(f, "\n{}", labels.backtrace_unsupported)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Renders only the outermost error, forwarding to its `Display` impl
+/// without allocating.
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.inner.error())
+    }
+}
+
+unsafe impl Send for Error {}
+unsafe impl Sync for Error {}
+
+impl Drop for Error {
+    fn drop(&mut self) {
+        // An Error beneath a layer of context is taken out and dropped in this
+        // loop rather than together with the layer, which would recurse once
+        // per layer and could overflow the stack for a very deep chain.
+        let mut next = unsafe { drop_error(&mut self.inner) };
+        while let Some(error) = next {
+            let mut inner = unsafe { ptr::read(&error.inner) };
+            mem::forget(error);
+            next = unsafe { drop_error(&mut inner) };
+        }
+    }
+}
+
+// Drops the error of an ErrorImpl in place, other than an Error wrapped in a
+// layer of context, which is moved out and returned instead of being dropped.
+//
+// Safety: the error must not be used or dropped again afterwards.
+unsafe fn drop_error(inner: &mut ErrorImpl<()>) -> Option<Error> {
+    if let Some(context) = inner.context {
+        if let Some(error) = (context.take_inner)(inner.error_mut()) {
+            return Some(error);
+        }
+    }
+    ptr::drop_in_place(inner.error_mut());
+    None
+}
+
+// repr C to ensure that `E` remains in the final position
+#[repr(C)]
+struct ErrorImpl<E> {
+    vtable: *const (),
+    // The type of the error field, unlike type_id.
+    object_type_id: TypeId,
+    type_id: TypeId,
+    type_name: &'static str,
+    // Whether the error is a MessageError, see Error::is_message.
+    message: bool,
+    into_boxed: unsafe fn(Box<ErrorImpl<()>>) -> Box<dyn StdError + Send + Sync>,
+    backtrace: Option<Backtrace>,
+    attachments: Option<Box<Attachments>>,
+    location: Option<&'static Location<'static>>,
+    root_cause: RootCause,
+    #[cfg(feature = "timestamp")]
+    created_at: SystemTime,
+    #[cfg(feature = "thread-info")]
+    origin_thread: Thread,
+    #[cfg(feature = "build-info")]
+    origin: Origin,
+    #[cfg(feature = "tracing")]
+    span: Option<&'static Metadata<'static>>,
+    context: Option<ContextVtable>,
+    error: E,
+}
+
+// Notes, the exit code, error code, severity, retryability and URL, related
+// errors, the recorded io::ErrorKind and appended sources. Most errors have
+// none of these, so they are allocated the first time one is set and an
+// ErrorImpl without any pays for a single pointer.
+#[derive(Default)]
+struct Attachments {
+    notes: Vec<Box<dyn Display + Send + Sync>>,
+    exit_code: Option<i32>,
+    code: Option<&'static str>,
+    severity: Option<Severity>,
+    retryable: bool,
+    url: Option<String>,
+    related: Vec<Error>,
+    #[cfg(feature = "std")]
+    io_kind: Option<io::ErrorKind>,
+    sources: Vec<Box<dyn StdError + Send + Sync>>,
+}
+
+// What an ErrorImpl without attachments reads as.
+static NO_ATTACHMENTS: Attachments = Attachments {
+    notes: Vec::new(),
+    exit_code: None,
+    code: None,
+    severity: None,
+    retryable: false,
+    url: None,
+    related: Vec::new(),
+    #[cfg(feature = "std")]
+    io_kind: None,
+    sources: Vec::new(),
+};
+
+// The attachments and the time, thread and process of creation belong to the
+// error as a whole rather than to one link of the chain, so they move up to
+// the new outermost layer when context is added. Every field of ErrorImpl of
+// that kind belongs here, so that it is moved along with the others. The
+// fields recorded by the optional features are set for every error, so they
+// are stored inline rather than with the attachments.
+struct Carried {
+    attachments: Option<Box<Attachments>>,
+    #[cfg(feature = "timestamp")]
+    created_at: SystemTime,
+    #[cfg(feature = "thread-info")]
+    origin_thread: Thread,
+    #[cfg(feature = "build-info")]
+    origin: Origin,
+    #[cfg(feature = "tracing")]
+    span: Option<&'static Metadata<'static>>,
+}
+
+impl<E> ErrorImpl<E> {
+    fn attachments(&self) -> &Attachments {
+        self.attachments.as_deref().unwrap_or(&NO_ATTACHMENTS)
+    }
+
+    fn attachments_mut(&mut self) -> &mut Attachments {
+        self.attachments.get_or_insert_with(Box::default)
+    }
+
+    fn take_carried(&mut self) -> Carried {
+        Carried {
+            attachments: self.attachments.take(),
+            #[cfg(feature = "timestamp")]
+            created_at: self.created_at,
+            #[cfg(feature = "thread-info")]
+            origin_thread: self.origin_thread.clone(),
+            #[cfg(feature = "build-info")]
+            origin: self.origin,
+            #[cfg(feature = "tracing")]
+            span: self.span,
+        }
+    }
+
+    fn set_carried(&mut self, carried: Carried) {
+        let Carried {
+            attachments,
+            #[cfg(feature = "timestamp")]
+            created_at,
+            #[cfg(feature = "thread-info")]
+            origin_thread,
+            #[cfg(feature = "build-info")]
+            origin,
+            #[cfg(feature = "tracing")]
+            span,
+        } = carried;
+        self.attachments = attachments;
+        #[cfg(feature = "timestamp")]
+        {
+            self.created_at = created_at;
+        }
+        #[cfg(feature = "thread-info")]
+        {
+            self.origin_thread = origin_thread;
+        }
+        #[cfg(feature = "build-info")]
+        {
+            self.origin = origin;
+        }
+        #[cfg(feature = "tracing")]
+        {
+            self.span = span;
+        }
+    }
+}
+
+// The root cause remembered by Error::root_cause, pointing into the chain of
+// the ErrorImpl that holds it. The two halves of the fat pointer are stored
+// separately, guarded by a state that makes sure only one thread writes them
+// and that they are only read once written; a thread that finds another one
+// in the middle of writing them walks the chain itself.
+struct RootCause {
+    state: AtomicUsize,
+    data: AtomicPtr<()>,
+    vtable: AtomicPtr<()>,
+}
+
+const ROOT_CAUSE_UNSET: usize = 0;
+const ROOT_CAUSE_WRITING: usize = 1;
+const ROOT_CAUSE_SET: usize = 2;
+
+impl RootCause {
+    fn new() -> Self {
+        RootCause {
+            state: AtomicUsize::new(ROOT_CAUSE_UNSET),
+            data: AtomicPtr::new(ptr::null_mut()),
+            vtable: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    fn get(&self) -> Option<*const (dyn StdError + 'static)> {
+        if self.state.load(Ordering::Acquire) != ROOT_CAUSE_SET {
+            return None;
+        }
+        let object = TraitObject {
+            data: self.data.load(Ordering::Relaxed),
+            vtable: self.vtable.load(Ordering::Relaxed),
+        };
+        Some(unsafe { mem::transmute::<TraitObject, *const (dyn StdError + 'static)>(object) })
+    }
+
+    fn set(&self, root_cause: &(dyn StdError + 'static)) {
+        let result = self.state.compare_exchange(
+            ROOT_CAUSE_UNSET,
+            ROOT_CAUSE_WRITING,
+            Ordering::Acquire,
+            Ordering::Relaxed,
+        );
+        if result.is_err() {
+            return;
+        }
+        let object = unsafe { mem::transmute::<&dyn StdError, TraitObject>(root_cause) };
+        self.data.store(object.data as *mut (), Ordering::Relaxed);
+        self.vtable
+            .store(object.vtable as *mut (), Ordering::Relaxed);
+        self.state.store(ROOT_CAUSE_SET, Ordering::Release);
+    }
+
+    fn clear(&mut self) {
+        *self.state.get_mut() = ROOT_CAUSE_UNSET;
+    }
+}
+
+// Operations that reach through a ContextError to the error it wraps. The
+// concrete type of the ContextError is erased in ErrorImpl<()>, so these are
+// remembered alongside it in the form of function pointers.
+#[derive(Clone, Copy)]
+struct ContextVtable {
+    inner_error: for<'a> fn(&'a (dyn StdError + Send + Sync + 'static)) -> Option<&'a Error>,
+    // Type of the wrapped error for the purpose of downcasting, that is M
+    // rather than MessageError<M>.
+    inner_type_id: TypeId,
+    inner_type_name: &'static str,
+    source_mut: for<'a> fn(&'a mut (dyn StdError + Send + Sync + 'static)) -> Option<LinkMut<'a>>,
+    into_source: unsafe fn(Box<ErrorImpl<()>>) -> (String, Error),
+    take_inner: unsafe fn(&mut (dyn StdError + Send + Sync + 'static)) -> Option<Error>,
+    context_value: for<'a> fn(&'a (dyn StdError + Send + Sync + 'static)) -> Option<&'a dyn Any>,
+}
+
+fn context_value<'a, C, E>(error: &'a (dyn StdError + Send + Sync + 'static)) -> Option<&'a dyn Any>
+where
+    C: Display + Send + Sync + 'static,
+    E: Send + Sync + 'static,
+    ContextError<E, C>: StdError,
+{
+    Some(&error.downcast_ref::<ContextError<E, C>>()?.context)
+}
+
+fn context_anyhow_inner_error<'a, C>(
+    error: &'a (dyn StdError + Send + Sync + 'static),
+) -> Option<&'a Error>
+where
+    C: Display + Send + Sync + 'static,
+{
+    Some(&error.downcast_ref::<ContextError<Error, C>>()?.error)
+}
+
+// Context added by Error::from_context or the message fast path wraps an error
+// that is not itself an Error.
+fn no_inner_error<'a>(_error: &'a (dyn StdError + Send + Sync + 'static)) -> Option<&'a Error> {
+    None
+}
+
+fn context_source_mut<'a, C, E>(
+    error: &'a mut (dyn StdError + Send + Sync + 'static),
+) -> Option<LinkMut<'a>>
+where
+    C: Display + Send + Sync + 'static,
+    E: StdError + Send + Sync + 'static,
+{
+    let error = &mut error.downcast_mut::<ContextError<E, C>>()?.error;
+    Some(LinkMut {
+        error,
+        type_id: TypeId::of::<E>(),
+        context: None,
+    })
+}
+
+fn context_anyhow_source_mut<'a, C>(
+    error: &'a mut (dyn StdError + Send + Sync + 'static),
+) -> Option<LinkMut<'a>>
+where
+    C: Display + Send + Sync + 'static,
+{
+    let error = &mut error.downcast_mut::<ContextError<Error, C>>()?.error;
+    Some(error.inner.link_mut())
+}
+
+fn context_message_source_mut<'a, C, M>(
+    error: &'a mut (dyn StdError + Send + Sync + 'static),
+) -> Option<LinkMut<'a>>
+where
+    C: Display + Send + Sync + 'static,
+    M: Display + Debug + Send + Sync + 'static,
+{
+    let error = &mut error
+        .downcast_mut::<ContextError<MessageError<M>, C>>()?
+        .error;
+    Some(LinkMut {
+        error,
+        type_id: TypeId::of::<M>(),
+        context: None,
+    })
+}
+
+// Safety: the ErrorImpl must have been constructed from ContextError<E, C>.
+unsafe fn context_into_source<C, E>(inner: Box<ErrorImpl<()>>) -> (String, Error)
+where
+    C: Display + Send + Sync + 'static,
+    E: StdError + Send + Sync + 'static,
+{
+    let inner = mem::transmute::<Box<ErrorImpl<()>>, Box<ErrorImpl<ContextError<E, C>>>>(inner);
+    let ContextError { error, context } = inner.error;
+    // The error is only rewrapped for the sake of being downcast, so it does
+    // not need a backtrace.
+    let error = Error::construct(error, TypeId::of::<E>(), type_name::<E>(), None);
+    (context.to_string(), error)
+}
+
+// Safety: the ErrorImpl must have been constructed from
+// ContextError<MessageError<M>, C>.
+unsafe fn context_message_into_source<C, M>(inner: Box<ErrorImpl<()>>) -> (String, Error)
+where
+    C: Display + Send + Sync + 'static,
+    M: Display + Debug + Send + Sync + 'static,
+{
+    let inner = mem::transmute::<
+        Box<ErrorImpl<()>>,
+        Box<ErrorImpl<ContextError<MessageError<M>, C>>>,
+    >(inner);
+    let ContextError { error, context } = inner.error;
+    let error = Error::construct(error, TypeId::of::<M>(), type_name::<M>(), None);
+    (context.to_string(), error)
+}
+
+// Safety: the ErrorImpl must have been constructed from ContextError<Error, C>.
+unsafe fn context_anyhow_into_source<C>(inner: Box<ErrorImpl<()>>) -> (String, Error)
+where
+    C: Display + Send + Sync + 'static,
+{
+    let inner = mem::transmute::<Box<ErrorImpl<()>>, Box<ErrorImpl<ContextError<Error, C>>>>(inner);
+    let ContextError { error, context } = inner.error;
+    (context.to_string(), error)
+}
+
+// Drops the context of a ContextError<Error, C> in place and moves the Error
+// out of it.
+//
+// Safety: the ContextError must not be used or dropped again afterwards.
+unsafe fn context_anyhow_take_inner<C>(
+    error: &mut (dyn StdError + Send + Sync + 'static),
+) -> Option<Error>
+where
+    C: Display + Send + Sync + 'static,
+{
+    let error = error.downcast_mut::<ContextError<Error, C>>()?;
+    ptr::drop_in_place(&mut error.context);
+    Some(ptr::read(&error.error))
+}
+
+unsafe fn no_take_inner(_error: &mut (dyn StdError + Send + Sync + 'static)) -> Option<Error> {
+    None
+}
+
+// Safety: the ErrorImpl must have been constructed from E.
+unsafe fn object_into_boxed<E>(inner: Box<ErrorImpl<()>>) -> Box<dyn StdError + Send + Sync>
+where
+    E: StdError + Send + Sync + 'static,
+{
+    let inner = mem::transmute::<Box<ErrorImpl<()>>, Box<ErrorImpl<E>>>(inner);
+    Box::new(inner.error)
+}
+
+// repr C to ensure that transmuting from trait objects is safe
+#[repr(C)]
+struct TraitObject {
+    data: *const (),
+    vtable: *const (),
+}
+
+#[repr(transparent)]
+struct MessageError<M>(M);
+
+impl<M> Debug for MessageError<M>
+where
+    M: Display + Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Debug::fmt(&self.0, f)
+    }
+}
+
+impl<M> Display for MessageError<M>
+where
+    M: Display + Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+impl<M> StdError for MessageError<M> where M: Display + Debug + 'static {}
+
+// Holds the value passed to Error::from_display, which need not implement
+// Debug. Transparent so that it can be downcast to the value it wraps.
+#[repr(transparent)]
+struct DisplayError<D>(D);
+
+impl<D> Debug for DisplayError<D>
+where
+    D: Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+impl<D> Display for DisplayError<D>
+where
+    D: Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+// Holds the box passed to Error::from_boxed. Transparent so that it can be
+// downcast to the box it wraps.
+#[repr(transparent)]
+struct BoxedError(Box<dyn StdError + Send + Sync>);
+
+impl Debug for BoxedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Debug::fmt(&self.0, f)
+    }
+}
+
+impl Display for BoxedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+impl StdError for BoxedError {
+    #[cfg(backtrace)]
+    fn provide<'a>(&'a self, request: &mut Request<'a>) {
+        self.0.provide(request);
+    }
+
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        self.0.source()
+    }
+}
+
+// The errors grouped by Error::from_errors or Error::from_errors_with, along
+// with the header given to the latter.
+struct AggregateError {
+    header: Option<Box<dyn Display + Send + Sync>>,
+    errors: Vec<Error>,
+}
+
+impl Debug for AggregateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(&self.errors).finish()
+    }
+}
+
+impl Display for AggregateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.header, self.errors.len()) {
+            (Some(header), _) => Display::fmt(header, f),
+            (None, 1) => f.write_str("1 error occurred"),
+            (None, n) => write!(f, "{} errors occurred", n),
+        }
+    }
+}
+
+impl StdError for AggregateError {}
+
+// The messages of an error's chain on one line, joined by ": ", as sub-errors
+// and related errors are rendered in the Debug representation.
+struct ChainLine<'a>(&'a Error);
+
+impl Display for ChainLine<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut chain = self.0.chain();
+        if let Some(error) = chain.next() {
+            write!(f, "{}", error)?;
+        }
+        for error in chain {
+            write!(f, ": {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+// Capture a backtrace for a new error, unless the filter installed by
+// set_backtrace_filter rejects the error.
+#[cfg(backtrace)]
+fn capture_backtrace(error: &(dyn StdError + 'static)) -> Backtrace {
+    match backtrace_filter() {
+        Some(filter) if !filter(error) => Backtrace::disabled(),
+        _ => Backtrace::capture(),
+    }
+}
+
+// Whether the value of RUST_LIB_BACKTRACE asks for backtraces, which are not
+// available on this toolchain. Same as the standard library, any value other
+// than "0" does.
+#[cfg(all(feature = "std", not(backtrace)))]
+fn backtrace_requested(var: Option<OsString>) -> bool {
+    match var {
+        Some(var) => var != "0",
+        None => false,
+    }
+}
+
+// Prefix of a link in the Debug representation with set_show_types.
+struct TypeAnnotation(Option<&'static str>);
+
+impl Display for TypeAnnotation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(type_name) => write!(f, "[{}] ", type_name),
+            None => Ok(()),
+        }
+    }
+}
+
+// Display adapter returned by Error::report.
+struct ErrorReport<'a>(&'a Error);
+
+impl Display for ErrorReport<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Debug::fmt(self.0, f)
+    }
+}
+
+// 64-bit FNV-1a, used by Error::fingerprint. Implemented here rather than
+// using core::hash::Hasher implementations, none of which promise the same
+// output across Rust releases.
+struct Fingerprint(u64);
+
+impl Fingerprint {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    fn new() -> Self {
+        Fingerprint(Self::OFFSET_BASIS)
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(Self::PRIME);
+        }
+    }
+}
+
+impl fmt::Write for Fingerprint {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_bytes(s.as_bytes());
+        Ok(())
+    }
+}
+
+// Writes to the formatter with every line after the first indented by
+// `indent` spaces.
+struct Indented<'a, 'b> {
+    f: &'a mut fmt::Formatter<'b>,
+    indent: usize,
+}
+
+impl fmt::Write for Indented<'_, '_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for (i, line) in s.split('\n').enumerate() {
+            if i > 0 {
+                writeln!(self.f)?;
+                if !line.is_empty() {
+                    write!(self.f, "{:indent$}", "", indent = self.indent)?;
+                }
+            }
+            self.f.write_str(line)?;
+        }
+        Ok(())
+    }
+}
+
+// Adapter for handing an Error to APIs that need a std::error::Error.
+#[repr(transparent)]
+struct ErrorWrapper(Error);
+
+impl Debug for ErrorWrapper {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Debug::fmt(&self.0, f)
+    }
+}
+
+impl Display for ErrorWrapper {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+impl StdError for ErrorWrapper {
+    #[cfg(backtrace)]
+    fn provide<'a>(&'a self, request: &mut Request<'a>) {
+        self.0.provide(request);
+    }
+
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        self.0.source()
+    }
+}
+
+impl ErrorImpl<()> {
+    fn link_mut(&mut self) -> LinkMut<'_> {
+        let type_id = self.type_id;
+        let context = self.context;
+        LinkMut {
+            error: self.error_mut(),
+            type_id,
+            context,
+        }
+    }
+
+    fn error(&self) -> &(dyn StdError + Send + Sync + 'static) {
+        let object = TraitObject {
+            data: &self.error,
+            vtable: self.vtable,
+        };
+
+        unsafe { mem::transmute::<TraitObject, &(dyn StdError + Send + Sync + 'static)>(object) }
+    }
+
+    fn error_mut(&mut self) -> &mut (dyn StdError + Send + Sync + 'static) {
+        // The caller may change the error's sources.
+        self.root_cause.clear();
+        let object = TraitObject {
+            data: &mut self.error,
+            vtable: self.vtable,
+        };
+
+        unsafe {
+            mem::transmute::<TraitObject, &mut (dyn StdError + Send + Sync + 'static)>(object)
+        }
+    }
+}
+
+/// Iterator of a chain of source errors.
+///
+/// This type is the iterator returned by [`Error::chain`].
+///
+/// # Example
+///
+/// ```
+/// use anyhow::Error;
+/// use std::io;
+///
+/// pub fn underlying_io_error_kind(error: &Error) -> Option<io::ErrorKind> {
+///     for cause in error.chain() {
+///         if let Some(io_error) = cause.downcast_ref::<io::Error>() {
+///             return Some(io_error.kind());
+///         }
+///     }
+///     None
+/// }
+/// ```
+pub struct Chain<'a> {
+    next: Option<&'a (dyn StdError + 'static)>,
+    // Brent's cycle detection: the errors yielded are compared against one
+    // error of the current run of source() calls, which is moved forward to
+    // the latest error whenever the number of steps since it was last moved
+    // reaches a power of two.
+    tortoise: &'a (dyn StdError + 'static),
+    power: usize,
+    steps: usize,
+    sources: slice::Iter<'a, Box<dyn StdError + Send + Sync>>,
+}
+
+impl<'a> Chain<'a> {
+    // A chain starting at an error that is not an Error.
+    pub(crate) fn new(head: &'a (dyn StdError + 'static)) -> Self {
+        Chain {
+            next: Some(head),
+            tortoise: head,
+            power: 1,
+            steps: 0,
+            sources: [].iter(),
+        }
+    }
+}
+
+impl<'a> Iterator for Chain<'a> {
+    type Item = &'a (dyn StdError + 'static);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = match self.next.take() {
+            Some(next) => next,
+            None => {
+                let next = &**self.sources.next()?;
+                self.tortoise = next;
+                self.power = 1;
+                self.steps = 0;
+                next
+            }
+        };
+        self.next = match next.source() {
+            Some(source) if same_error(source, self.tortoise) => None,
+            Some(source) => {
+                self.steps += 1;
+                if self.steps == self.power {
+                    self.tortoise = source;
+                    self.power *= 2;
+                    self.steps = 0;
+                }
+                Some(source)
+            }
+            None => None,
+        };
+        Some(next)
+    }
+}
+
+// Once next is None and the sources are used up, both stay that way.
+impl FusedIterator for Chain<'_> {}
+
+// Whether two trait objects are the same error. An error can start at the same
+// address as one of its fields, which may be its source, so the vtables are
+// compared as well as the addresses. Vtables are not guaranteed to be unique,
+// so this may miss that the errors are the same, but never the other way
+// round; a cycle is still detected on its second lap, as references obtained
+// from the same source() implementation share a vtable.
+fn same_error(a: &(dyn StdError + 'static), b: &(dyn StdError + 'static)) -> bool {
+    let a = unsafe { mem::transmute::<&dyn StdError, TraitObject>(a) };
+    let b = unsafe { mem::transmute::<&dyn StdError, TraitObject>(b) };
+    a.data == b.data && a.vtable == b.vtable
+}
+
+/// Iterator of mutable references to the errors in a chain.
+///
+/// This type is the iterator returned by [`Error::chain_mut`], whose
+/// documentation describes which errors it visits.
+pub struct ChainMut<'a> {
+    next: Option<LinkMut<'a>>,
+    sources: slice::IterMut<'a, Box<dyn StdError + Send + Sync>>,
+}
+
+struct LinkMut<'a> {
+    error: &'a mut (dyn StdError + Send + Sync + 'static),
+    type_id: TypeId,
+    context: Option<ContextVtable>,
+}
+
+impl<'a> Iterator for ChainMut<'a> {
+    type Item = &'a mut (dyn StdError + 'static);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(link) = self.next.take() {
+            let context = match link.context {
+                Some(context) => context,
+                None => return Some(link.error),
+            };
+            self.next = (context.source_mut)(link.error);
+        }
+        let source = self.sources.next()?;
+        Some(&mut **source)
+    }
+}
+
+#[cfg(test)]
+mod repr_correctness {
+    use super::*;
+    use std::marker::Unpin;
+    use std::mem;
+    use std::sync::atomic::Ordering::SeqCst;
+    use std::sync::atomic::{AtomicBool, AtomicUsize};
+    use std::sync::Arc;
+
+    // An error that counts how many times it is dropped.
+    #[derive(Debug)]
+    struct CountDrops(Arc<AtomicUsize>);
+
+    impl StdError for CountDrops {}
+
+    impl Display for CountDrops {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "does something")
+        }
+    }
+
+    impl Drop for CountDrops {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, SeqCst);
+        }
+    }
+
+    #[test]
+    fn size_of_error() {
+        assert_eq!(mem::size_of::<Error>(), mem::size_of::<usize>());
+    }
+
+    #[test]
+    fn size_of_option_error() {
+        assert_eq!(mem::size_of::<Option<Error>>(), mem::size_of::<usize>());
+        assert_eq!(mem::size_of::<Result<(), Error>>(), mem::size_of::<usize>(),);
+    }
+
+    #[test]
+    fn error_autotraits() {
+        fn assert<E: Unpin + Send + Sync + 'static>() {}
+        assert::<Error>();
+    }
+
+    #[test]
+    fn drop_works() {
+        #[derive(Debug)]
+        struct DetectDrop {
+            has_dropped: Arc<AtomicBool>,
+        }
+
+        impl StdError for DetectDrop {}
+
+        impl Display for DetectDrop {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "does something")
+            }
+        }
+
+        impl Drop for DetectDrop {
+            fn drop(&mut self) {
+                let already_dropped = self.has_dropped.swap(true, SeqCst);
+                assert!(!already_dropped);
+            }
+        }
+
+        let has_dropped = Arc::new(AtomicBool::new(false));
+
+        drop(Error::from(DetectDrop {
+            has_dropped: has_dropped.clone(),
+        }));
+
+        assert!(has_dropped.load(SeqCst));
+    }
+
+    #[test]
+    fn drop_deep_chain() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let mut error = Error::msg("oh no!").context(CountDrops(drops.clone()));
+        for _ in 0..100_000 {
+            error = error.context(CountDrops(drops.clone()));
+        }
+        drop(error);
+        assert_eq!(drops.load(SeqCst), 100_001);
+    }
+
+    #[test]
+    fn downcast_drops_once() {
+        let error_drops = Arc::new(AtomicUsize::new(0));
+        let note_drops = Arc::new(AtomicUsize::new(0));
+
+        let error = Error::new(CountDrops(error_drops.clone()))
+            .note(CountDrops(note_drops.clone()))
+            .wrap_source(CountDrops(note_drops.clone()));
+        let error = error.downcast::<CountDrops>().unwrap();
+        assert_eq!(error_drops.load(SeqCst), 0);
+        assert_eq!(note_drops.load(SeqCst), 2);
+
+        drop(error);
+        assert_eq!(error_drops.load(SeqCst), 1);
+    }
+
+    #[test]
+    fn into_inner_drops_once() {
+        let drops = Arc::new(AtomicUsize::new(0));
+
+        let inner = Error::new(CountDrops(drops.clone())).into_inner();
+        assert_eq!(drops.load(SeqCst), 0);
+        drop(inner);
+        assert_eq!(drops.load(SeqCst), 1);
+
+        let boxed = Box::new(CountDrops(drops.clone()));
+        let inner = Error::from_boxed(boxed).into_inner();
+        assert_eq!(drops.load(SeqCst), 1);
+        drop(inner);
+        assert_eq!(drops.load(SeqCst), 2);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{anyhow, Context, Error, Labels, Severity};
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+    use std::error::Error as StdError;
+    use std::fmt::{self, Display};
+    use std::io;
+    use std::panic::{self, Location};
+    use std::ptr;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn notes_in_order() {
+        let error = anyhow!("oh no!")
+            .note("Try running with --verbose")
+            .note("See the manual for details");
+
+        let expected = "\
+oh no!
+
+Note: Try running with --verbose
+Note: See the manual for details
+";
+        assert!(format!("{:?}", error).starts_with(expected));
+        assert_eq!(error.chain().count(), 1);
+    }
+
+    #[test]
+    fn notes_after_causes() {
+        let error = anyhow!("oh no!")
+            .note("Try running with --verbose")
+            .context("failed to connect");
+
+        let expected = "\
+failed to connect
+
+Caused by:
+    oh no!
+
+Note: Try running with --verbose
+";
+        assert!(format!("{:?}", error).starts_with(expected));
+        assert_eq!(error.chain().count(), 2);
+    }
+
+    #[test]
+    fn notes_multiline() {
+        let error = anyhow!("oh no!").note("Try one of:\n--verbose\n--offline");
+
+        let expected = "\
+oh no!
+
+Note: Try one of:
+        --verbose
+        --offline
+";
+        assert!(format!("{:8?}", error).starts_with(expected));
+    }
+
+    #[test]
+    fn wrap_source() {
+        let not_found = io::Error::new(io::ErrorKind::NotFound, "file not found");
+        let denied = io::Error::new(io::ErrorKind::PermissionDenied, "access denied");
+        let error = anyhow!("oh no!")
+            .context("failed to load plugin")
+            .wrap_source(not_found)
+            .wrap_source(denied);
+
+        let chain: Vec<String> = error.chain().map(ToString::to_string).collect();
+        assert_eq!(
+            chain,
+            [
+                "failed to load plugin",
+                "oh no!",
+                "file not found",
+                "access denied"
+            ],
+        );
+        assert_eq!(error.root_cause().to_string(), "access denied");
+
+        let error = error.context("failed to start");
+        assert_eq!(error.chain().count(), 5);
+        assert_eq!(error.root_cause().to_string(), "access denied");
+    }
+
+    #[test]
+    fn chain_mut() {
+        #[derive(Debug)]
+        struct LocalizedError(&'static str);
+
+        impl Display for LocalizedError {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str(self.0)
+            }
+        }
+
+        impl StdError for LocalizedError {}
+
+        let mut error = Error::new(LocalizedError("file not found"))
+            .context("failed to read config")
+            .context("failed to start")
+            .wrap_source(LocalizedError("access denied"));
+
+        assert_eq!(error.chain_mut().count(), 2);
+        for cause in error.chain_mut() {
+            let cause = cause.downcast_mut::<LocalizedError>().unwrap();
+            cause.0 = match cause.0 {
+                "file not found" => "fichier introuvable",
+                "access denied" => "accès refusé",
+                other => other,
+            };
+        }
+
+        let chain: Vec<String> = error.chain().map(ToString::to_string).collect();
+        assert_eq!(
+            chain,
+            [
+                "failed to start",
+                "failed to read config",
+                "fichier introuvable",
+                "accès refusé",
+            ],
+        );
+    }
+
+    #[test]
+    fn chain_enumerated() {
+        let error = Error::msg("oh no!")
+            .context("failed to read config")
+            .context("failed to start");
+        let links: Vec<(usize, String)> = error
+            .chain_enumerated()
+            .map(|(depth, cause)| (depth, cause.to_string()))
+            .collect();
+        assert_eq!(
+            links,
+            [
+                (0, "failed to start".to_owned()),
+                (1, "failed to read config".to_owned()),
+                (2, "oh no!".to_owned()),
+            ],
+        );
+    }
+
+    #[test]
+    fn chain_fused() {
+        fn assert_fused<I: core::iter::FusedIterator>() {}
+        assert_fused::<super::Chain>();
+
+        let error = anyhow!("oh no!").context("failed");
+        let mut chain = error.chain();
+        assert_eq!(chain.next().unwrap().to_string(), "failed");
+        assert_eq!(chain.next().unwrap().to_string(), "oh no!");
+        for _ in 0..3 {
+            assert!(chain.next().is_none());
+        }
+    }
+
+    #[test]
+    fn chain_with_cycle() {
+        #[derive(Debug)]
+        struct SelfCycle;
+
+        impl Display for SelfCycle {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("self cycle")
+            }
+        }
+
+        impl StdError for SelfCycle {
+            fn source(&self) -> Option<&(dyn StdError + 'static)> {
+                Some(self)
+            }
+        }
+
+        #[derive(Debug)]
+        struct Ping;
+
+        #[derive(Debug)]
+        struct Pong;
+
+        static PING: Ping = Ping;
+        static PONG: Pong = Pong;
+
+        impl Display for Ping {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("ping")
+            }
+        }
+
+        impl Display for Pong {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("pong")
+            }
+        }
+
+        impl StdError for Ping {
+            fn source(&self) -> Option<&(dyn StdError + 'static)> {
+                Some(&PONG)
+            }
+        }
+
+        impl StdError for Pong {
+            fn source(&self) -> Option<&(dyn StdError + 'static)> {
+                Some(&PING)
+            }
+        }
+
+        let error = Error::new(SelfCycle);
+        assert!(error.chain().count() <= 2);
+        assert!(error.chain().all(|cause| cause.is::<SelfCycle>()));
+
+        let error = Error::new(Ping).context("failed").wrap_source(SelfCycle);
+        let chain: Vec<String> = error.chain().map(ToString::to_string).collect();
+        assert_eq!(chain[..4], ["failed", "ping", "pong", "ping"]);
+        assert!(chain.len() <= 8);
+        assert_eq!(error.root_cause().to_string(), "self cycle");
+    }
+
+    #[test]
+    fn chain_eq() {
+        let error = Error::msg("oh no!")
+            .context("failed to read config")
+            .context("failed to start");
+        assert!(error.chain_eq(&["failed to start", "failed to read config", "oh no!"]));
+
+        assert!(!error.chain_eq(&["failed to start", "failed to read config", "oh dear"]));
+        assert!(!error.chain_eq(&["failed to start", "failed to read config"]));
+        assert!(!error.chain_eq(&["failed to start", "failed to read config", "oh no!", ""]));
+        assert!(!error.chain_eq(&[]));
+    }
+
+    #[test]
+    fn chain_strings_rev() {
+        let error = Error::msg("oh no!")
+            .context("failed to read config")
+            .context("failed to start");
+        assert_eq!(
+            error.chain_strings_rev(),
+            ["oh no!", "failed to read config", "failed to start"],
+        );
+    }
+
+    #[test]
+    fn into_chain_strings() {
+        let error = Error::msg("oh no!")
+            .context("failed to read config")
+            .context("failed to start");
+        let strings = error.into_chain_strings();
+        assert_eq!(
+            strings,
+            ["failed to start", "failed to read config", "oh no!"]
+        );
+    }
+
+    #[test]
+    fn fingerprint() {
+        let a = Error::msg("oh no!").context("failed to start");
+        let b = anyhow!("oh {}!", "no").context("failed to start");
+        assert_eq!(a.fingerprint(), b.fingerprint());
+
+        let c = Error::msg("oh dear!").context("failed to start");
+        assert_ne!(a.fingerprint(), c.fingerprint());
+
+        let d = Error::msg("failed to start").context("oh no!");
+        assert_ne!(a.fingerprint(), d.fingerprint());
+
+        let e = Error::msg("ab").context("c");
+        let f = Error::msg("a").context("bc");
+        assert_ne!(e.fingerprint(), f.fingerprint());
+    }
+
+    #[test]
+    fn sources() {
+        let error = Error::msg("oh no!")
+            .context("failed to read config")
+            .context("failed to start")
+            .wrap_source(io::Error::from(io::ErrorKind::NotFound));
+        let sources: Vec<_> = error.sources().collect();
+        let chain: Vec<_> = error.chain().collect();
+        assert_eq!(sources.len(), 4);
+        assert!(sources.iter().zip(&chain).all(|(a, b)| ptr::eq(*a, *b)));
+    }
+
+    #[test]
+    fn map_messages() {
+        let error = anyhow!("disk full")
+            .context("failed to save")
+            .context("failed to quit");
+        let shouted = error.map_messages(str::to_uppercase);
+        assert!(shouted.chain_eq(&["FAILED TO QUIT", "FAILED TO SAVE", "DISK FULL"]));
+        assert!(error.chain_eq(&["failed to quit", "failed to save", "disk full"]));
+    }
+
+    #[test]
+    fn chain_len() {
+        let error = Error::msg("oh no!");
+        assert_eq!(error.chain_len(), 1);
+
+        let error = error
+            .context("failed to read config")
+            .context("failed to start");
+        assert_eq!(error.chain_len(), 3);
+
+        let error = error.wrap_source(io::Error::from(io::ErrorKind::NotFound));
+        assert_eq!(error.chain_len(), 4);
+        assert_eq!(error.chain_len(), error.chain().count());
+    }
+
+    #[test]
+    fn base() {
+        #[derive(Debug)]
+        struct LoadError(io::Error);
+
+        impl Display for LoadError {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("failed to load plugin")
+            }
+        }
+
+        impl StdError for LoadError {
+            fn source(&self) -> Option<&(dyn StdError + 'static)> {
+                Some(&self.0)
+            }
+        }
+
+        let error = Error::new(io::Error::from(io::ErrorKind::NotFound))
+            .context("failed to read config")
+            .context("failed to start");
+        let base = error.base();
+        assert!(base.is::<io::Error>());
+        assert!(ptr::eq(base, error.root_cause()));
+
+        let error = Error::new(LoadError(io::Error::from(io::ErrorKind::NotFound)))
+            .context("failed to start");
+        assert!(error.base().is::<LoadError>());
+        assert!(error.root_cause().is::<io::Error>());
+
+        let error = anyhow!("oh no!");
+        assert!(ptr::eq(error.base(), &*error));
+    }
+
+    #[test]
+    fn real_causes() {
+        let error = Error::new(io::Error::from(io::ErrorKind::NotFound))
+            .context("failed to read config")
+            .context("failed to start")
+            .wrap_source(io::Error::from(io::ErrorKind::PermissionDenied));
+        let causes: Vec<io::ErrorKind> = error
+            .real_causes()
+            .map(|link| link.downcast_ref::<io::Error>().unwrap().kind())
+            .collect();
+        assert_eq!(
+            causes,
+            [io::ErrorKind::NotFound, io::ErrorKind::PermissionDenied],
+        );
+
+        let error = anyhow!("oh no!");
+        assert_eq!(error.real_causes().count(), 1);
+    }
+
+    #[test]
+    fn root_cause_depth() {
+        let error = Error::msg("oh no!");
+        assert_eq!(error.root_cause_depth(), 0);
+        assert!(ptr::eq(
+            error.root_cause(),
+            error.chain().nth(error.root_cause_depth()).unwrap()
+        ));
+
+        let error = error
+            .context("failed to read config")
+            .context("failed to start");
+        assert_eq!(error.root_cause_depth(), 2);
+        assert!(ptr::eq(
+            error.root_cause(),
+            error.chain().nth(error.root_cause_depth()).unwrap()
+        ));
+    }
+
+    #[test]
+    fn root_cause_cached() {
+        use core::sync::atomic::{AtomicUsize, Ordering};
+
+        static SOURCE_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+        #[derive(Debug)]
+        struct Leaf;
+
+        impl Display for Leaf {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("leaf")
+            }
+        }
+
+        impl StdError for Leaf {
+            fn source(&self) -> Option<&(dyn StdError + 'static)> {
+                SOURCE_CALLS.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+
+        let mut error = Error::new(Leaf);
+        for i in 0..100 {
+            error = error.context(i);
+        }
+
+        // Only the first call walks the chain down to the leaf, and it does
+        // not allocate to remember what it found.
+        let (is_leaf, allocations) = total_allocations(|| error.root_cause().is::<Leaf>());
+        assert!(is_leaf);
+        assert_eq!(allocations, 0);
+        let walked = SOURCE_CALLS.load(Ordering::Relaxed);
+        assert!(walked > 0);
+        for _ in 0..100 {
+            assert_eq!(error.root_cause().to_string(), "leaf");
+        }
+        assert_eq!(SOURCE_CALLS.load(Ordering::Relaxed), walked);
+
+        // Extending the chain forgets the remembered root cause.
+        let mut error = error.wrap_source(io::Error::from(io::ErrorKind::NotFound));
+        assert!(error.root_cause().is::<io::Error>());
+
+        // So does replacing an error through a mutable reference.
+        *error.root_cause_mut().downcast_mut::<io::Error>().unwrap() =
+            io::Error::new(io::ErrorKind::NotFound, "config.toml not found");
+        assert_eq!(error.root_cause().to_string(), "config.toml not found");
+    }
+
+    #[test]
+    fn into_arc() {
+        let error = Error::msg("oh no!").context("failed to start");
+        let first = error.into_arc();
+        let second = Arc::clone(&first);
+        assert_eq!(first.to_string(), "failed to start");
+        assert_eq!(second.to_string(), "failed to start");
+        assert_eq!(second.source().unwrap().to_string(), "oh no!");
+        assert_eq!(Arc::strong_count(&first), 2);
+    }
+
+    #[test]
+    fn from_boxed_into_inner() {
+        #[derive(Debug)]
+        struct ConfigError {
+            line: usize,
+        }
+
+        impl Display for ConfigError {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "bad config on line {}", self.line)
+            }
+        }
+
+        impl StdError for ConfigError {}
+
+        let boxed: Box<dyn StdError + Send + Sync> = Box::new(ConfigError { line: 7 });
+        let address = &*boxed as *const dyn StdError as *const ();
+        let error = Error::from_boxed(boxed).note("check the syntax");
+        assert_eq!(error.to_string(), "bad config on line 7");
+
+        let inner = error.into_inner();
+        assert_eq!(&*inner as *const dyn StdError as *const (), address);
+        assert_eq!(inner.downcast_ref::<ConfigError>().unwrap().line, 7);
+    }
+
+    #[test]
+    fn into_inner() {
+        let error = Error::new(io::Error::from(io::ErrorKind::NotFound));
+        let inner = error.into_inner();
+        assert_eq!(
+            inner.downcast::<io::Error>().unwrap().kind(),
+            io::ErrorKind::NotFound
+        );
+
+        let error = Error::msg("oh no!").context("failed to start");
+        let inner = error.into_inner();
+        assert_eq!(inner.to_string(), "failed to start");
+        assert_eq!(inner.source().unwrap().to_string(), "oh no!");
+    }
+
+    #[test]
+    fn is_message() {
+        assert!(anyhow!("x").is_message());
+        assert!(anyhow!("{} not found", "x").is_message());
+        assert!(Error::msg(String::from("x")).is_message());
+        assert!(Error::from_display(1).is_message());
+
+        let io_error = io::Error::from(io::ErrorKind::NotFound);
+        assert!(!Error::new(io_error).is_message());
+        let boxed: Box<dyn StdError + Send + Sync> =
+            Box::new(io::Error::from(io::ErrorKind::NotFound));
+        assert!(!Error::from_boxed(boxed).is_message());
+
+        // Only the outermost layer counts.
+        assert!(!anyhow!("x").context("failed").is_message());
+        assert!(!Error::new(io::Error::from(io::ErrorKind::NotFound))
+            .context("failed")
+            .is_message());
+    }
+
+    #[test]
+    fn box_round_trip() {
+        #[derive(Debug, PartialEq)]
+        struct MyError(u32);
+
+        impl Display for MyError {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "my error {}", self.0)
+            }
+        }
+
+        impl StdError for MyError {}
+
+        let boxed: Box<dyn StdError + Send + Sync> = Error::new(MyError(1)).into();
+        assert_eq!(*boxed.downcast::<MyError>().unwrap(), MyError(1));
+
+        let boxed: Box<dyn StdError + Send> = Error::new(MyError(2)).into();
+        assert_eq!(*boxed.downcast::<MyError>().unwrap(), MyError(2));
+
+        let boxed: Box<dyn StdError> = Error::new(MyError(3)).into();
+        assert_eq!(*boxed.downcast::<MyError>().unwrap(), MyError(3));
+
+        let boxed = Error::from_boxed(Box::new(MyError(4))).into_inner();
+        assert_eq!(*boxed.downcast::<MyError>().unwrap(), MyError(4));
+    }
+
+    #[test]
+    fn question_mark_into_box() {
+        fn read_config() -> crate::Result<()> {
+            Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "config.toml not found",
+            ))
+            .context("failed to read config")
+        }
+
+        fn start() -> Result<(), Box<dyn StdError + Send + Sync>> {
+            read_config()?;
+            Ok(())
+        }
+
+        let expected = read_config().unwrap_err().into_chain_strings();
+        let error = start().unwrap_err();
+        assert_eq!(error.to_string(), "failed to read config");
+        let chain: Vec<String> = super::Chain::new(&*error)
+            .map(|link| link.to_string())
+            .collect();
+        assert_eq!(chain, expected);
+    }
+
+    #[test]
+    fn raw_round_trip() {
+        let error = Error::new(io::Error::from(io::ErrorKind::NotFound))
+            .context("failed to read config")
+            .note("Try running with --verbose");
+        let ptr = error.into_raw();
+        assert!(!ptr.is_null());
+
+        let error = unsafe { Error::from_raw(ptr) };
+        assert_eq!(error.to_string(), "failed to read config");
+        assert_eq!(error.chain_len(), 2);
+        assert!(error.root_cause().is::<io::Error>());
+        assert!(format!("{:?}", error).contains("Note: Try running with --verbose\n"));
+    }
+
+    #[test]
+    fn report() {
+        let error = anyhow!("connection refused")
+            .context("failed to fetch index")
+            .context("failed to update registry")
+            .note("Run with --offline to skip the update");
+
+        let report = error.report().to_string();
+        assert!(report.starts_with("failed to update registry\n"));
+        assert!(report.contains("Caused by:"));
+        assert!(report.contains("0: failed to fetch index\n"));
+        assert!(report.contains("1: connection refused\n"));
+        assert!(report.contains("Note: Run with --offline to skip the update\n"));
+        assert_eq!(report, format!("{:?}", error));
+    }
+
+    #[test]
+    fn new_with() {
+        let calls = Cell::new(0);
+        let key = String::from("port");
+        let error = Error::new_with(|| {
+            calls.set(calls.get() + 1);
+            format!("no such key: {}", key)
+        });
+        drop(key);
+        assert_eq!(calls.get(), 1);
+        assert_eq!(error.to_string(), "no such key: port");
+        assert!(error.is::<String>());
+    }
+
+    #[test]
+    fn root_cause_mut() {
+        let mut error = Error::new(io::Error::new(io::ErrorKind::NotFound, "oh no!"))
+            .context("failed to read config")
+            .context("failed to start");
+        let root = error.root_cause_mut().downcast_mut::<io::Error>().unwrap();
+        *root = io::Error::new(root.kind(), "config.toml not found");
+        assert_eq!(error.root_cause().to_string(), "config.toml not found");
+        assert_eq!(error.to_string(), "failed to start");
+
+        let mut error = error.wrap_source(io::Error::from(io::ErrorKind::PermissionDenied));
+        let root = error.root_cause_mut().downcast_mut::<io::Error>().unwrap();
+        assert_eq!(root.kind(), io::ErrorKind::PermissionDenied);
+
+        // The source of a foreign error is out of reach, so the foreign error
+        // itself is returned.
+        #[derive(Debug)]
+        struct Outer(io::Error);
+
+        impl Display for Outer {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("outer")
+            }
+        }
+
+        impl StdError for Outer {
+            fn source(&self) -> Option<&(dyn StdError + 'static)> {
+                Some(&self.0)
+            }
+        }
+
+        let mut error = Error::new(Outer(io::Error::from(io::ErrorKind::NotFound)));
+        assert!(error.root_cause().is::<io::Error>());
+        assert!(error.root_cause_mut().is::<Outer>());
+    }
+
+    #[test]
+    fn downcast_with_context() {
+        let error = Error::new(io::Error::new(io::ErrorKind::NotFound, "oh no!"))
+            .context("failed to read config")
+            .context("failed to start");
+
+        let (contexts, error) = error.downcast_with_context::<io::Error>().unwrap();
+        assert_eq!(contexts, ["failed to start", "failed to read config"]);
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        assert_eq!(error.to_string(), "oh no!");
+
+        let result: Result<(), io::Error> = Err(io::Error::new(io::ErrorKind::NotFound, "oh no!"));
+        let error = result.context("failed to read config").unwrap_err();
+        let error = error.context("failed to start");
+        let (contexts, error) = error.downcast_with_context::<io::Error>().unwrap();
+        assert_eq!(contexts, ["failed to start", "failed to read config"]);
+        assert_eq!(error.to_string(), "oh no!");
+    }
+
+    #[test]
+    fn downcast_or_context() {
+        let error = Error::new(io::Error::from(io::ErrorKind::NotFound));
+        let error = error
+            .downcast_or_context::<io::Error, _>("expected an I/O error")
+            .unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+
+        let error = anyhow!("oh no!")
+            .downcast_or_context::<io::Error, _>("expected an I/O error")
+            .unwrap_err();
+        assert!(error.chain_eq(&["expected an I/O error", "oh no!"]));
+    }
+
+    #[test]
+    fn context_local() {
+        use crate::ContextLocal;
+        use std::cell::Cell;
+
+        #[derive(Debug)]
+        struct LegacyError {
+            code: Cell<i32>,
+            source: io::Error,
+        }
+
+        impl Display for LegacyError {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "legacy error {}", self.code.get())
+            }
+        }
+
+        impl StdError for LegacyError {
+            fn source(&self) -> Option<&(dyn StdError + 'static)> {
+                Some(&self.source)
+            }
+        }
+
+        let result: Result<(), LegacyError> = Err(LegacyError {
+            code: Cell::new(7),
+            source: io::Error::new(io::ErrorKind::NotFound, "oh no!"),
+        });
+        let error = result.context_local("failed to start").unwrap_err();
+        assert!(error.chain_eq(&["failed to start", "legacy error 7", "oh no!"]));
+        assert!(error.is_context_frame(&*error));
+        assert!(!error.base().is::<io::Error>());
+        assert!(error.root_cause().downcast_ref::<io::Error>().is_none());
+    }
+
+    #[test]
+    fn downcast_ref_inner() {
+        let error = Error::new(io::Error::from(io::ErrorKind::NotFound))
+            .context("failed to read config")
+            .context("failed to start");
+        assert!(error.downcast_ref::<io::Error>().is_none());
+        let inner = error.downcast_ref_inner::<io::Error>().unwrap();
+        assert_eq!(inner.kind(), io::ErrorKind::NotFound);
+        assert!(error.downcast_ref_inner::<&str>().is_none());
+
+        let result: Result<(), io::Error> = Err(io::Error::from(io::ErrorKind::NotFound));
+        let error = result.context("failed to read config").unwrap_err();
+        assert!(error.downcast_ref_inner::<io::Error>().is_some());
+
+        let error = anyhow!("oh no!").context("failed to start");
+        assert_eq!(*error.downcast_ref_inner::<&str>().unwrap(), "oh no!");
+        assert!(error.downcast_ref_inner::<String>().is_none());
+
+        let error = anyhow!("oh no!");
+        assert_eq!(*error.downcast_ref_inner::<&str>().unwrap(), "oh no!");
+    }
+
+    #[test]
+    fn downcast_ref_transparent() {
+        let error = Error::new(io::Error::from(io::ErrorKind::NotFound))
+            .context("failed to read config")
+            .context("failed to start");
+        let inner = error.downcast_ref_transparent::<io::Error>().unwrap();
+        assert_eq!(inner.kind(), io::ErrorKind::NotFound);
+        assert!(error.downcast_ref::<io::Error>().is_none());
+    }
+
+    #[test]
+    fn context_downcast_ref() {
+        #[derive(Debug, PartialEq)]
+        struct RequestId(u64);
+
+        impl Display for RequestId {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "while handling request {}", self.0)
+            }
+        }
+
+        // Through each of the ways context is added.
+        let error = Error::new(io::Error::from(io::ErrorKind::NotFound)).context(RequestId(42));
+        assert_eq!(error.context_downcast_ref(), Some(&RequestId(42)));
+        assert!(error.downcast_ref::<RequestId>().is_none());
+
+        let error = anyhow!("oh no!").context(RequestId(42));
+        assert_eq!(error.context_downcast_ref(), Some(&RequestId(42)));
+
+        let result: Result<(), io::Error> = Err(io::Error::from(io::ErrorKind::NotFound));
+        let error = result.context(RequestId(42)).unwrap_err();
+        assert_eq!(error.context_downcast_ref(), Some(&RequestId(42)));
+
+        // Only the outermost context is looked at.
+        let error = error.context("failed to start");
+        assert!(error.context_downcast_ref::<RequestId>().is_none());
+        assert_eq!(
+            *error.context_downcast_ref::<&str>().unwrap(),
+            "failed to start"
+        );
+
+        assert!(anyhow!("oh no!").context_downcast_ref::<&str>().is_none());
+    }
+
+    #[test]
+    fn downcast_with_context_mismatch() {
+        let error = anyhow!("oh no!").context("failed to start");
+        let error = error.downcast_with_context::<io::Error>().unwrap_err();
+        assert_eq!(error.to_string(), "failed to start");
+        assert_eq!(error.chain().count(), 2);
+
+        let (contexts, message) = error.downcast_with_context::<&str>().unwrap();
+        assert_eq!(contexts, ["failed to start"]);
+        assert_eq!(message, "oh no!");
+    }
+
+    // Counts allocations per thread so that tests running in parallel do not
+    // see each other's. The number of allocations made by the constructors is
+    // part of this crate's performance contract, so new constructors should
+    // get a test in constructor_allocations below.
+    struct CountingAllocator;
+
+    thread_local! {
+        static LIVE_ALLOCATIONS: Cell<isize> = const { Cell::new(0) };
+        static TOTAL_ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    fn count_allocation(delta: isize) {
+        let _ = LIVE_ALLOCATIONS.try_with(|live| live.set(live.get() + delta));
+        if delta > 0 {
+            let _ = TOTAL_ALLOCATIONS.try_with(|total| total.set(total.get() + 1));
+        }
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            count_allocation(1);
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            count_allocation(-1);
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    fn live_allocations<R>(f: impl FnOnce() -> R) -> (R, isize) {
+        let before = LIVE_ALLOCATIONS.with(Cell::get);
+        let ret = f();
+        let after = LIVE_ALLOCATIONS.with(Cell::get);
+        (ret, after - before)
+    }
+
+    // Unlike live_allocations, this also counts allocations that were freed
+    // again before returning.
+    fn total_allocations<R>(f: impl FnOnce() -> R) -> (R, usize) {
+        let before = TOTAL_ALLOCATIONS.with(Cell::get);
+        let ret = f();
+        let after = TOTAL_ALLOCATIONS.with(Cell::get);
+        (ret, after - before)
+    }
+
+    // Capturing a backtrace allocates however much the environment asks for,
+    // so the allocation tests construct their errors without one. These
+    // constructors share everything else with Error::new and Error::msg.
+    fn quiet_msg(message: &'static str) -> Error {
+        crate::private::new_adhoc_quiet(message)
+    }
+
+    #[test]
+    fn constructor_allocations() {
+        let io_error = io::Error::from(io::ErrorKind::NotFound);
+        let (error, allocations) = total_allocations(|| Error::new_without_backtrace(io_error));
+        assert_eq!(allocations, 1);
+        assert!(error.is::<io::Error>());
+
+        let (error, allocations) = total_allocations(|| quiet_msg("oh no!"));
+        assert_eq!(allocations, 1);
+        assert_eq!(error.to_string(), "oh no!");
+
+        let io_error = io::Error::from(io::ErrorKind::NotFound);
+        let error = Error::new_without_backtrace(io_error);
+        let (error, allocations) = total_allocations(|| error.context("failed"));
+        assert_eq!(allocations, 1);
+        assert_eq!(error.chain().count(), 2);
+
+        let error = quiet_msg("oh no!");
+        let (error, allocations) = total_allocations(|| error.context("failed"));
+        assert_eq!(allocations, 1);
+        assert_eq!(error.chain().count(), 2);
+    }
+
+    #[test]
+    fn attachment_allocations() {
+        // The attachments are allocated when the first one is set, and are
+        // moved rather than copied when context is added.
+        let error = quiet_msg("oh no!");
+        let (error, allocations) = total_allocations(|| error.with_code("E_DISK"));
+        assert_eq!(allocations, 1);
+        let (error, allocations) = total_allocations(|| error.mark_retryable(true));
+        assert_eq!(allocations, 0);
+        let (error, allocations) = total_allocations(|| error.context("failed"));
+        assert_eq!(allocations, 1);
+        assert_eq!(error.code(), Some("E_DISK"));
+        assert!(error.is_retryable());
+    }
+
+    // Without backtraces, the public constructors are covered as well.
+    #[cfg(not(backtrace))]
+    #[test]
+    fn public_constructor_allocations() {
+        let io_error = io::Error::from(io::ErrorKind::NotFound);
+        let (error, allocations) = total_allocations(|| Error::new(io_error));
+        assert_eq!(allocations, 1);
+        assert!(error.is::<io::Error>());
+
+        let (error, allocations) = total_allocations(|| Error::msg("oh no!"));
+        assert_eq!(allocations, 1);
+        assert_eq!(error.to_string(), "oh no!");
+
+        let (error, allocations) = total_allocations(|| anyhow!("oh no!"));
+        assert_eq!(allocations, 1);
+        assert_eq!(error.to_string(), "oh no!");
+    }
+
+    #[test]
+    fn display_allocations() {
+        use std::fmt::Write;
+
+        let error = Error::msg("oh no!");
+        let (display, allocations) = total_allocations(|| format!("{}", error));
+        assert_eq!(allocations, 1);
+        assert_eq!(display, "oh no!");
+
+        let error = Error::msg("oh no!".to_owned()).context("failed");
+        let mut display = String::with_capacity(16);
+        let ((), allocations) = total_allocations(|| write!(display, "{}", error).unwrap());
+        assert_eq!(allocations, 0);
+        assert_eq!(display, "failed");
+    }
+
+    #[test]
+    fn message_context_allocations() {
+        let (error, allocations) = live_allocations(|| quiet_msg("oh no!").context("failed"));
+        assert_eq!(allocations, 1);
+        let chain: Vec<String> = error.chain().map(ToString::to_string).collect();
+        assert_eq!(chain, ["failed", "oh no!"]);
+
+        let (error, allocations) = live_allocations(|| {
+            crate::private::new_adhoc_quiet("oh no!".to_owned()).context("failed")
+        });
+        assert_eq!(allocations, 2);
+        let chain: Vec<String> = error.chain().map(ToString::to_string).collect();
+        assert_eq!(chain, ["failed", "oh no!"]);
+
+        let (error, allocations) = live_allocations(|| {
+            Error::new_without_backtrace(io::Error::from(io::ErrorKind::NotFound)).context("failed")
+        });
+        assert_eq!(allocations, 2);
+        assert_eq!(error.chain().count(), 2);
+    }
+
+    #[test]
+    fn context_on_ok_is_lazy() {
+        static DISPLAYED: AtomicBool = AtomicBool::new(false);
+
+        struct DetectDisplay;
+
+        impl Display for DetectDisplay {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                DISPLAYED.store(true, Ordering::SeqCst);
+                f.write_str("failed")
+            }
+        }
+
+        let (value, allocations) = live_allocations(|| {
+            let result: Result<i32, io::Error> = Ok(1);
+            result.context(DetectDisplay).unwrap()
+        });
+        assert_eq!(value, 1);
+        assert_eq!(allocations, 0);
+
+        let (value, allocations) = live_allocations(|| {
+            let result: Result<i32, Error> = Ok(1);
+            result.context(DetectDisplay).unwrap()
+        });
+        assert_eq!(value, 1);
+        assert_eq!(allocations, 0);
+
+        assert!(!DISPLAYED.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn message_context_preserves_attachments() {
+        let error = Error::msg("oh no!")
+            .note("Try again")
+            .with_exit_code(2)
+            .context("failed");
+        assert_eq!(error.exit_code(), 2);
+        assert!(format!("{:?}", error)
+            .starts_with("failed\n\nCaused by:\n    oh no!\n\nNote: Try again\n"));
+
+        let (contexts, message) = error.downcast_with_context::<&str>().unwrap();
+        assert_eq!(contexts, ["failed"]);
+        assert_eq!(message, "oh no!");
+
+        // Downcasts to String like a message, but is not stored as one.
+        let error = Error::from_display(String::from("oh no!"))
+            .note("Try again")
+            .context("failed");
+        assert_eq!(error.exit_code(), 1);
+        let chain: Vec<String> = error.chain().map(ToString::to_string).collect();
+        assert_eq!(chain, ["failed", "oh no!"]);
+        assert_eq!(error.downcast_ref_inner::<String>().unwrap(), "oh no!");
+        assert!(format!("{:?}", error).contains("\nNote: Try again\n"));
+    }
+
+    #[test]
+    fn source() {
+        let error = anyhow!("oh no!");
+        assert!(error.source().is_none());
+
+        let error = error.context("failed to read config");
+        let source = error.source().unwrap();
+        assert_eq!(source.to_string(), "oh no!");
+        assert!(ptr::eq(source, error.chain().nth(1).unwrap()));
+
+        let error = error.wrap_source(io::Error::from(io::ErrorKind::NotFound));
+        assert!(ptr::eq(
+            error.source().unwrap(),
+            error.chain().nth(1).unwrap()
+        ));
+    }
+
+    #[cfg(backtrace)]
+    #[test]
+    fn backtrace_frames() {
+        use std::backtrace::BacktraceStatus;
+
+        let error = anyhow!("oh no!");
+        let frames: Vec<_> = error.backtrace_frames().collect();
+        if error.backtrace().status() == BacktraceStatus::Captured {
+            assert!(!frames.is_empty());
+            assert!(frames
+                .iter()
+                .any(|frame| frame.symbol().contains("backtrace_frames")));
+        } else {
+            assert!(frames.is_empty());
+        }
+    }
+
+    #[cfg(backtrace)]
+    #[test]
+    fn backtrace_frames_start_at_call_site() {
+        use std::backtrace::BacktraceStatus;
+
+        let error = Error::new(io::Error::from(io::ErrorKind::NotFound));
+        if error.backtrace().status() != BacktraceStatus::Captured {
+            return;
+        }
+        let first = error.backtrace_frames().next().unwrap();
+        assert!(first
+            .symbol()
+            .contains("backtrace_frames_start_at_call_site"));
+        assert!(!error.backtrace_frames().any(|frame| frame
+            .symbol()
+            .starts_with("anyhow::error::<impl anyhow::Error>")));
+    }
+
+    #[cfg(backtrace)]
+    #[test]
+    fn backtrace_filter() {
+        use std::backtrace::{Backtrace, BacktraceStatus};
+
+        #[derive(Debug)]
+        struct Routine;
+
+        impl Display for Routine {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("routine")
+            }
+        }
+
+        impl StdError for Routine {}
+
+        // The filter is global, so it must not allocate or it would throw off
+        // the allocation counts of tests running in parallel.
+        crate::set_backtrace_filter(|error| !error.is::<Routine>());
+
+        let error = Error::new(Routine);
+        assert_eq!(error.backtrace().status(), BacktraceStatus::Disabled);
+        let error = Error::new(Routine).context("failed");
+        assert_eq!(error.backtrace().status(), BacktraceStatus::Disabled);
+
+        // Anything else is captured as usual.
+        let expected = Backtrace::capture().status();
+        let error = anyhow!("oh no!");
+        assert_eq!(error.backtrace().status(), expected);
+    }
+
+    /// An error that provides a backtrace of its own, for the tests that need
+    /// one regardless of how the environment configures capturing.
+    #[cfg(backtrace)]
+    #[derive(Debug)]
+    struct TracedError(std::backtrace::Backtrace);
+
+    #[cfg(backtrace)]
+    impl Display for TracedError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("traced")
+        }
+    }
+
+    #[cfg(backtrace)]
+    impl StdError for TracedError {
+        fn provide<'a>(&'a self, request: &mut std::error::Request<'a>) {
+            request.provide_ref::<std::backtrace::Backtrace>(&self.0);
+        }
+    }
+
+    #[cfg(backtrace)]
+    #[test]
+    fn backtraces() {
+        use std::backtrace::{Backtrace, BacktraceStatus};
+
+        let error = anyhow!("oh no!")
+            .context("failed")
+            .wrap_source(TracedError(Backtrace::force_capture()));
+        let backtraces = error.backtraces();
+        assert_eq!(backtraces.len(), 2);
+        assert!(ptr::eq(backtraces[0], error.backtrace()));
+        assert_eq!(backtraces[1].status(), BacktraceStatus::Captured);
+
+        let error = Error::new(TracedError(Backtrace::force_capture())).context("failed");
+        let backtraces = error.backtraces();
+        assert_eq!(backtraces.len(), 1);
+        assert!(ptr::eq(backtraces[0], error.backtrace()));
+    }
+
+    #[cfg(backtrace)]
+    #[test]
+    fn request_backtrace() {
+        use std::backtrace::Backtrace;
+
+        let error = anyhow!("oh no!").context("failed");
+        let backtrace = error.request_ref::<Backtrace>().unwrap();
+        assert!(ptr::eq(backtrace, error.backtrace()));
+    }
+
+    #[cfg(backtrace)]
+    #[test]
+    fn has_backtrace() {
+        use std::backtrace::Backtrace;
+
+        // Whether Backtrace::capture captures is decided by the environment
+        // once per process, so both outcomes are produced by an error that
+        // provides its own backtrace instead.
+        let error = Error::new(TracedError(Backtrace::force_capture())).context("failed");
+        assert!(error.has_backtrace());
+
+        let error = Error::new(TracedError(Backtrace::disabled())).context("failed");
+        assert!(!error.has_backtrace());
+    }
+
+    #[cfg(all(feature = "std", not(backtrace)))]
+    #[test]
+    fn backtrace_unsupported() {
+        use super::backtrace_requested;
+        use std::env;
+        use std::ffi::OsString;
+
+        assert!(!backtrace_requested(None));
+        assert!(!backtrace_requested(Some(OsString::from("0"))));
+        assert!(backtrace_requested(Some(OsString::from("1"))));
+        assert!(backtrace_requested(Some(OsString::from("full"))));
+
+        // Setting the variable here would affect tests running concurrently,
+        // so only the case that applies to this process is checked.
+        let error = anyhow!("oh no!");
+        let note = format!("\n{}\n", Labels::default().backtrace_unsupported);
+        let requested = backtrace_requested(env::var_os("RUST_LIB_BACKTRACE"));
+        assert_eq!(format!("{:?}", error).ends_with(&note), requested);
+    }
+
+    #[cfg(backtrace)]
+    #[test]
+    fn backtrace_hint() {
+        // The setting made by crate::set_backtrace_hint is global, so leave it
+        // alone for the sake of tests running concurrently.
+        struct Hinted<'a>(&'a Error, bool);
+
+        impl Display for Hinted<'_> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                self.0
+                    .debug_with_labels(f, &Labels::default(), false, self.1)
+            }
+        }
+
+        let error = Error::builder()
+            .message("oh no!")
+            .capture_backtrace(false)
+            .build();
+        let hint = Labels::default().backtrace_disabled;
+        assert!(Hinted(&error, true).to_string().contains(hint));
+        assert_eq!(Hinted(&error, false).to_string(), "oh no!\n");
+    }
+
+    #[cfg(not(backtrace))]
+    #[test]
+    fn has_backtrace() {
+        let error = anyhow!("oh no!").context("failed");
+        assert!(!error.has_backtrace());
+    }
+
+    #[cfg(backtrace)]
+    #[test]
+    fn context_preserves_backtrace() {
+        use std::backtrace::{Backtrace, BacktraceStatus};
+
+        let error = Error::new(TracedError(Backtrace::force_capture()));
+        let original: *const Backtrace = &error.downcast_ref::<TracedError>().unwrap().0;
+        let error = error.context("first").context("second");
+        assert_eq!(error.backtrace().status(), BacktraceStatus::Captured);
+        assert!(ptr::eq(error.backtrace(), original));
+
+        // The message fast path moves the backtrace into the new allocation,
+        // so compare the captured frames rather than the address.
+        let error = Error::msg("oh no!");
+        let original = error.backtrace().to_string();
+        let error = error.context("first").context("second");
+        assert_eq!(error.backtrace().to_string(), original);
+    }
+
+    #[cfg(backtrace)]
+    #[test]
+    fn request_through_chain() {
+        use std::error::Request;
+
+        #[derive(Debug)]
+        struct StatusError(u16);
+
+        impl Display for StatusError {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "status {}", self.0)
+            }
+        }
+
+        impl StdError for StatusError {
+            fn provide<'a>(&'a self, request: &mut Request<'a>) {
+                request.provide_value::<u16>(self.0);
+            }
+        }
+
+        let error = Error::new(StatusError(404))
+            .context("failed to fetch")
+            .context("failed to sync");
+        assert_eq!(error.request_value::<u16>(), Some(404));
+        assert_eq!(error.request_value::<u32>(), None);
+
+        let error = anyhow!("oh no!").wrap_source(StatusError(503));
+        assert_eq!(error.request_value::<u16>(), Some(503));
+    }
+
+    #[cfg(feature = "timestamp")]
+    #[test]
+    fn created_at() {
+        use std::time::SystemTime;
+
+        let before = SystemTime::now();
+        let error = anyhow!("oh no!");
+        let after = SystemTime::now();
+        let created_at = error.created_at();
+        assert!(before <= created_at && created_at <= after);
+
+        let error = error.context("failed");
+        assert_eq!(error.created_at(), created_at);
+
+        let error = Error::new(io::Error::from(io::ErrorKind::NotFound)).context("failed");
+        assert!(created_at <= error.created_at());
+    }
+
+    #[cfg(feature = "thread-info")]
+    #[test]
+    fn origin_thread() {
+        use std::thread;
+
+        let (error, id) = thread::Builder::new()
+            .name("worker".to_owned())
+            .spawn(|| (anyhow!("oh no!"), thread::current().id()))
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_ne!(id, thread::current().id());
+        assert_eq!(error.origin_thread(), Some(id));
+        assert_eq!(error.origin_thread_name(), Some("worker"));
+
+        let error = error.context("failed");
+        assert_eq!(error.origin_thread(), Some(id));
+    }
+
+    #[cfg(feature = "build-info")]
+    #[test]
+    fn origin() {
+        let error = anyhow!("oh no!").context("failed");
+        let origin = error.origin();
+        assert_eq!(origin.pid, std::process::id());
+        assert_eq!(origin.version, env!("CARGO_PKG_VERSION"));
+    }
+
+    #[cfg(all(feature = "std", not(feature = "thread-info")))]
+    #[test]
+    fn origin_thread_disabled() {
+        let error = anyhow!("oh no!");
+        assert_eq!(error.origin_thread(), None);
+        assert_eq!(error.origin_thread_name(), None);
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn span_trace() {
+        use std::sync::Mutex;
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Metadata, Subscriber};
+        use tracing_core::span::Current;
+
+        // Just enough of a subscriber for Span::current to work.
+        #[derive(Default)]
+        struct Spans {
+            created: Mutex<Vec<&'static Metadata<'static>>>,
+            entered: Mutex<Vec<Id>>,
+        }
+
+        impl Subscriber for Spans {
+            fn enabled(&self, _metadata: &Metadata) -> bool {
+                true
+            }
+
+            fn new_span(&self, span: &Attributes) -> Id {
+                let mut created = self.created.lock().unwrap();
+                created.push(span.metadata());
+                Id::from_u64(created.len() as u64)
+            }
+
+            fn record(&self, _span: &Id, _values: &Record) {}
+
+            fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+            fn event(&self, _event: &Event) {}
+
+            fn enter(&self, span: &Id) {
+                self.entered.lock().unwrap().push(span.clone());
+            }
+
+            fn exit(&self, _span: &Id) {
+                self.entered.lock().unwrap().pop();
+            }
+
+            fn current_span(&self) -> Current {
+                match self.entered.lock().unwrap().last() {
+                    Some(id) => {
+                        let metadata = self.created.lock().unwrap()[id.into_u64() as usize - 1];
+                        Current::new(id.clone(), metadata)
+                    }
+                    None => Current::none(),
+                }
+            }
+        }
+
+        #[tracing::instrument]
+        fn load_config() -> Error {
+            anyhow!("oh no!")
+        }
+
+        let error = tracing::subscriber::with_default(Spans::default(), load_config);
+        let span = error.span_trace().unwrap();
+        assert_eq!(span.name(), "load_config");
+        assert_eq!(span.target(), "anyhow::error::tests");
+
+        let error = error.context("failed to start");
+        assert_eq!(error.span_trace().map(Metadata::name), Some("load_config"));
+        assert!(format!("{:?}", error).contains(
+            "\nSpan trace:\n    anyhow::error::tests::load_config\n        at src/error.rs:"
+        ));
+
+        let error = tracing::subscriber::with_default(Spans::default(), || anyhow!("oh no!"));
+        assert!(error.span_trace().is_none());
+    }
+
+    #[test]
+    fn chain_downcast() {
+        let not_found = io::Error::new(io::ErrorKind::NotFound, "file not found");
+        let denied = io::Error::new(io::ErrorKind::PermissionDenied, "access denied");
+        let error = Error::new(not_found)
+            .context("failed to read config")
+            .wrap_source(denied);
+
+        let kinds: Vec<io::ErrorKind> = error
+            .chain_downcast::<io::Error>()
+            .map(io::Error::kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            [io::ErrorKind::NotFound, io::ErrorKind::PermissionDenied],
+        );
+        assert_eq!(error.chain_downcast::<fmt::Error>().count(), 0);
+    }
+
+    #[test]
+    fn downcast_without_display() {
+        struct Plain {
+            _value: u32,
+        }
+
+        let mut error = anyhow!("oh no!");
+        assert!(!error.is::<Plain>());
+        assert!(error.downcast_ref::<Plain>().is_none());
+        assert!(error.downcast_mut::<Plain>().is_none());
+        let error = match error.downcast::<Plain>() {
+            Ok(_) => panic!("unexpected downcast to Plain"),
+            Err(error) => error,
+        };
+        assert_eq!(error.downcast_ref::<&str>(), Some(&"oh no!"));
     }
 
-    /// Downcast this error object by mutable reference.
-    pub fn downcast_mut<E>(&mut self) -> Option<&mut E>
-    where
-        E: Display + Debug + Send + Sync + 'static,
-    {
-        if self.is::<E>() {
-            unsafe { Some(&mut *(self.inner.error_mut() as *mut dyn StdError as *mut E)) }
-        } else {
-            None
-        }
+    #[test]
+    fn is_context_frame() {
+        let error = Error::new(io::Error::from(io::ErrorKind::NotFound))
+            .context("failed to read config")
+            .context("failed to start")
+            .wrap_source(io::Error::from(io::ErrorKind::PermissionDenied));
+        let frames: Vec<bool> = error
+            .chain()
+            .map(|link| error.is_context_frame(link))
+            .collect();
+        assert_eq!(frames, [true, true, false, false]);
+
+        let error = Error::msg("oh no!").context("failed to read config");
+        let frames: Vec<bool> = error
+            .chain()
+            .map(|link| error.is_context_frame(link))
+            .collect();
+        assert_eq!(frames, [true, false]);
+
+        let result: Result<(), io::Error> = Err(io::Error::from(io::ErrorKind::NotFound));
+        let error = result.context("failed to read config").unwrap_err();
+        let root = error.root_cause();
+        assert!(root.is::<io::Error>());
+        assert!(error.is_context_frame(&*error));
+        assert!(!error.is_context_frame(root));
+
+        let other = anyhow!("failed to read config").context("failed to start");
+        assert!(!error.is_context_frame(&*other));
+        assert!(!Error::msg("oh no!").is_context_frame(&*other));
     }
-}
 
-#[cfg(not(backtrace))]
-pub(crate) enum Backtrace {}
+    #[test]
+    fn find_downcast_ref() {
+        let error = Error::new(io::Error::from(io::ErrorKind::NotFound))
+            .context("failed to read config")
+            .context("failed to start");
+        let (depth, io_error) = error.find_downcast_ref::<io::Error>().unwrap();
+        assert_eq!(depth, 2);
+        assert_eq!(io_error.kind(), io::ErrorKind::NotFound);
+        assert!(error.find_downcast_ref::<fmt::Error>().is_none());
+    }
 
-impl<E> From<E> for Error
-where
-    E: StdError + Send + Sync + 'static,
-{
-    fn from(error: E) -> Self {
-        Error::new(error)
+    #[test]
+    fn find_any() {
+        let denied = io::Error::new(io::ErrorKind::PermissionDenied, "access denied");
+        let not_found = io::Error::new(io::ErrorKind::NotFound, "file not found");
+        let error = Error::new(denied)
+            .context("failed to read config")
+            .wrap_source(not_found);
+
+        let found = error.find_any(|cause| {
+            let cause = cause.downcast_ref::<io::Error>()?;
+            if cause.kind() == io::ErrorKind::NotFound {
+                Some(cause.to_string())
+            } else {
+                None
+            }
+        });
+        assert_eq!(found.as_deref(), Some("file not found"));
+
+        let mut visited = 0;
+        let found = error.find_any(|_| {
+            visited += 1;
+            None::<()>
+        });
+        assert_eq!(found, None);
+        assert_eq!(visited, 3);
     }
-}
 
-impl Deref for Error {
-    type Target = dyn StdError + Send + Sync + 'static;
+    #[test]
+    fn default_exit_code() {
+        let error = anyhow!("oh no!");
+        assert_eq!(error.exit_code(), 1);
+    }
 
-    fn deref(&self) -> &Self::Target {
-        self.inner.error()
+    #[test]
+    fn custom_exit_code() {
+        let error = anyhow!("oh no!").with_exit_code(64);
+        assert_eq!(error.exit_code(), 64);
+
+        let error = error.context("failed to parse arguments");
+        assert_eq!(error.exit_code(), 64);
     }
-}
 
-impl DerefMut for Error {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        self.inner.error_mut()
+    #[test]
+    fn error_code() {
+        let error = anyhow!("oh no!");
+        assert_eq!(error.code(), None);
+
+        let error = error.with_code("E_TIMEOUT");
+        assert_eq!(error.code(), Some("E_TIMEOUT"));
+
+        let error = error.context("failed to fetch").context("failed to start");
+        assert_eq!(error.code(), Some("E_TIMEOUT"));
+
+        let error = Error::new(io::Error::from(io::ErrorKind::TimedOut))
+            .with_code("E_IO")
+            .context("failed to fetch");
+        assert_eq!(error.code(), Some("E_IO"));
+
+        let error = error.with_code("E_FETCH");
+        assert_eq!(error.code(), Some("E_FETCH"));
     }
-}
 
-impl Debug for Error {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        writeln!(f, "{}", self.inner.error())?;
+    #[test]
+    fn severity() {
+        let error = anyhow!("oh no!");
+        assert_eq!(error.severity(), Severity::Error);
 
-        let mut chain = self.chain().skip(1).enumerate().peekable();
-        if let Some((n, error)) = chain.next() {
-            write!(f, "\nCaused by:\n    ")?;
-            if chain.peek().is_some() {
-                write!(f, "{}: ", n)?;
-            }
-            writeln!(f, "{}", error)?;
-            for (n, error) in chain {
-                writeln!(f, "    {}: {}", n, error)?;
-            }
-        }
+        let error = error.with_severity(Severity::Warning);
+        assert_eq!(error.severity(), Severity::Warning);
 
-        #[cfg(backtrace)]
-        {
-            let backtrace = self.backtrace();
-            match backtrace.status() {
-                BacktraceStatus::Captured => {
-                    writeln!(f, "\n{}", backtrace)?;
-                }
-                BacktraceStatus::Disabled => {
-                    writeln!(
-                        f,
-                        "\nBacktrace disabled; run with RUST_LIB_BACKTRACE=1 environment variable to display a backtrace"
-                    )?;
-                }
-                _ => {}
-            }
-        }
+        let error = error.context("failed to fetch").context("failed to start");
+        assert_eq!(error.severity(), Severity::Warning);
 
-        Ok(())
+        let error = Error::new(io::Error::from(io::ErrorKind::TimedOut))
+            .with_severity(Severity::Warning)
+            .context("failed to fetch")
+            .with_severity(Severity::Info)
+            .context("failed to start");
+        assert_eq!(error.severity(), Severity::Info);
     }
-}
 
-impl Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.inner.error())
+    #[test]
+    fn retryable() {
+        let error = anyhow!("oh no!");
+        assert!(!error.is_retryable());
+
+        let error = error.mark_retryable(true).context("failed to fetch");
+        assert!(error.is_retryable());
+
+        let error = error.mark_retryable(false).context("failed to start");
+        assert!(!error.is_retryable());
+
+        let result: Result<(), io::Error> = Err(io::Error::from(io::ErrorKind::TimedOut));
+        let error = result
+            .retryable_context("failed to fetch")
+            .context("failed to start")
+            .unwrap_err();
+        assert!(error.is_retryable());
     }
-}
 
-unsafe impl Send for Error {}
-unsafe impl Sync for Error {}
+    #[test]
+    fn related() {
+        let cleanup = anyhow!("database is locked").context("rollback failed");
+        let error = anyhow!("connection reset")
+            .add_related(cleanup)
+            .add_related(anyhow!("failed to remove lock file"))
+            .context("transfer failed");
+        let related: Vec<String> = error.related().iter().map(ToString::to_string).collect();
+        assert_eq!(related, ["rollback failed", "failed to remove lock file"]);
+        assert_eq!(error.chain().count(), 2);
 
-impl Drop for Error {
-    fn drop(&mut self) {
-        unsafe { ptr::drop_in_place(self.inner.error_mut()) }
+        let expected = "\
+transfer failed
+
+Caused by:
+    connection reset
+
+Additionally:
+    rollback failed: database is locked
+    failed to remove lock file
+";
+        assert!(error.report().to_string().starts_with(expected));
     }
-}
 
-// repr C to ensure that `E` remains in the final position
-#[repr(C)]
-struct ErrorImpl<E> {
-    vtable: *const (),
-    type_id: TypeId,
-    backtrace: Option<Backtrace>,
-    error: E,
-}
+    #[test]
+    fn sub_errors() {
+        let error = Error::from_errors([
+            anyhow!("name is empty"),
+            anyhow!("out of range").context("age is invalid"),
+        ]);
+        assert!(error.is_aggregate());
+        let sub_errors: Vec<String> = error.sub_errors().iter().map(ToString::to_string).collect();
+        assert_eq!(sub_errors, ["name is empty", "age is invalid"]);
+        assert_eq!(error.chain().count(), 1);
 
-// repr C to ensure that transmuting from trait objects is safe
-#[repr(C)]
-struct TraitObject {
-    data: *const (),
-    vtable: *const (),
-}
+        let error = error.context("invalid form");
+        assert!(error.is_aggregate());
+        assert_eq!(error.sub_errors().len(), 2);
+        let expected = "\
+invalid form
 
-#[repr(transparent)]
-struct MessageError<M>(M);
+Caused by:
+    2 errors occurred
 
-impl<M> Debug for MessageError<M>
-where
-    M: Display + Debug,
-{
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        Debug::fmt(&self.0, f)
+Errors:
+    0: name is empty
+    1: age is invalid: out of range
+";
+        assert!(error.report().to_string().starts_with(expected));
+
+        let error = anyhow!("oh no!");
+        assert!(!error.is_aggregate());
+        assert!(error.sub_errors().is_empty());
     }
-}
 
-impl<M> Display for MessageError<M>
-where
-    M: Display + Debug,
-{
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        Display::fmt(&self.0, f)
+    #[test]
+    fn sub_errors_with_header() {
+        let error = Error::from_errors_with(
+            "3 validation errors occurred",
+            [
+                anyhow!("name is empty"),
+                anyhow!("age is negative"),
+                anyhow!("not an address").context("email is invalid"),
+            ],
+        );
+        assert_eq!(error.to_string(), "3 validation errors occurred");
+        assert_eq!(error.sub_errors().len(), 3);
+        let expected = "\
+3 validation errors occurred
+
+Errors:
+    0: name is empty
+    1: age is negative
+    2: email is invalid: not an address
+";
+        assert!(error.report().to_string().starts_with(expected));
     }
-}
 
-impl<M> StdError for MessageError<M> where M: Display + Debug + 'static {}
+    #[test]
+    fn with_message() {
+        let error = Error::new(io::Error::from(io::ErrorKind::NotFound))
+            .context("failed to read /etc/app/secrets.toml")
+            .with_message("failed to load configuration");
+        assert_eq!(error.to_string(), "failed to load configuration");
+        let chain: Vec<String> = error.chain().map(ToString::to_string).collect();
+        assert_eq!(chain[1], "failed to read /etc/app/secrets.toml");
+        assert!(error.chain().nth(2).unwrap().is::<io::Error>());
+    }
 
-impl ErrorImpl<()> {
-    fn error(&self) -> &(dyn StdError + Send + Sync + 'static) {
-        let object = TraitObject {
-            data: &self.error,
-            vtable: self.vtable,
-        };
+    #[test]
+    fn context_at() {
+        let location = Location::caller();
+        let error = anyhow!("file not found")
+            .context_at("failed to load config", location)
+            .context("failed to start");
+        let locations: Vec<_> = error
+            .chain()
+            .map(|link| error.link_location(link))
+            .collect();
+        assert_eq!(locations, [None, Some(location), None]);
+    }
 
-        unsafe { mem::transmute::<TraitObject, &(dyn StdError + Send + Sync + 'static)>(object) }
+    #[test]
+    fn url() {
+        let error = anyhow!("certificate expired");
+        assert_eq!(error.url(), None);
+
+        let error = error
+            .note("Check the system clock")
+            .with_url("https://example.com/docs/tls")
+            .context("failed to connect");
+        assert_eq!(error.url(), Some("https://example.com/docs/tls"));
+        let expected = "\
+failed to connect
+
+Caused by:
+    certificate expired
+
+Note: Check the system clock
+
+See: https://example.com/docs/tls
+";
+        assert!(error.report().to_string().starts_with(expected));
     }
 
-    fn error_mut(&mut self) -> &mut (dyn StdError + Send + Sync + 'static) {
-        let object = TraitObject {
-            data: &mut self.error,
-            vtable: self.vtable,
-        };
+    #[test]
+    fn context_owned() {
+        let name = String::from("config.toml");
+        let borrowed: &str = &name;
+        let error = anyhow!("oh no!").context_owned(borrowed);
+        drop(name);
 
-        unsafe {
-            mem::transmute::<TraitObject, &mut (dyn StdError + Send + Sync + 'static)>(object)
+        assert!(error.chain_eq(&["config.toml", "oh no!"]));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn context_kind() {
+        let error = Error::new(io::Error::new(io::ErrorKind::NotFound, "oh no!"));
+        assert_eq!(error.io_kind(), None);
+
+        let error = error.context_kind("failed to read config");
+        assert_eq!(error.io_kind(), Some(io::ErrorKind::NotFound));
+        assert_eq!(error.to_string(), "failed to read config");
+
+        let error = error.context("failed to start");
+        assert_eq!(error.io_kind(), Some(io::ErrorKind::NotFound));
+
+        let error = error.context_kind("failed to run");
+        assert_eq!(error.io_kind(), Some(io::ErrorKind::NotFound));
+        assert_eq!(error.chain().count(), 4);
+
+        let error = anyhow!("oh no!").context_kind("failed to start");
+        assert_eq!(error.io_kind(), None);
+    }
+
+    #[test]
+    fn from_display() {
+        struct Status(u16);
+
+        impl Display for Status {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "status {}", self.0)
+            }
         }
+
+        let error = Error::from_display(Status(503));
+        assert_eq!(error.to_string(), "status 503");
+        assert_eq!(format!("{:?}", error.root_cause()), "status 503");
+        assert_eq!(error.downcast_ref::<Status>().unwrap().0, 503);
+
+        let error = error.context("failed to fetch");
+        assert!(error.chain_eq(&["failed to fetch", "status 503"]));
     }
-}
 
-/// Iterator of a chain of source errors.
-///
-/// This type is the iterator returned by [`Error::chain`].
-///
-/// # Example
-///
-/// ```
-/// use anyhow::Error;
-/// use std::io;
-///
-/// pub fn underlying_io_error_kind(error: &Error) -> Option<io::ErrorKind> {
-///     for cause in error.chain() {
-///         if let Some(io_error) = cause.downcast_ref::<io::Error>() {
-///             return Some(io_error.kind());
-///         }
-///     }
-///     None
-/// }
-/// ```
-pub struct Chain<'a> {
-    next: Option<&'a (dyn StdError + 'static)>,
-}
+    #[cfg(feature = "std")]
+    #[test]
+    fn io_error_kind() {
+        let error = Error::new(io::Error::from(io::ErrorKind::PermissionDenied))
+            .context("failed to open config")
+            .context("failed to start");
+        assert_eq!(error.io_error_kind(), Some(io::ErrorKind::PermissionDenied));
+        assert_eq!(error.io_kind(), None);
 
-impl<'a> Iterator for Chain<'a> {
-    type Item = &'a (dyn StdError + 'static);
+        let error = anyhow!("oh no!").context("failed to start");
+        assert_eq!(error.io_error_kind(), None);
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let next = self.next.take()?;
-        self.next = next.source();
-        Some(next)
+    #[test]
+    fn from_panic_message() {
+        let payload = panic::catch_unwind(|| panic!("boom")).unwrap_err();
+        let error = Error::from_panic(payload);
+        assert_eq!(error.to_string(), "boom");
+
+        let payload = panic::catch_unwind(|| panic!("boom {}", 1)).unwrap_err();
+        let error = Error::from_panic(payload);
+        assert_eq!(error.to_string(), "boom 1");
     }
-}
 
-#[cfg(test)]
-mod repr_correctness {
-    use super::*;
-    use std::marker::Unpin;
-    use std::mem;
-    use std::sync::atomic::AtomicBool;
-    use std::sync::atomic::Ordering::SeqCst;
-    use std::sync::Arc;
+    #[test]
+    fn from_panic_other_payload() {
+        let payload = panic::catch_unwind(|| panic::panic_any(1)).unwrap_err();
+        let error = Error::from_panic(payload);
+        assert_eq!(error.to_string(), "panic occurred");
+    }
 
+    #[cfg(feature = "std")]
     #[test]
-    fn size_of_error() {
-        assert_eq!(mem::size_of::<Error>(), mem::size_of::<usize>());
+    fn into_io_error_with_io_cause() {
+        let cause = io::Error::new(io::ErrorKind::NotFound, "oh no!");
+        let error = Error::new(cause).context("failed to read config");
+
+        let io_error = io::Error::from(error);
+        assert_eq!(io_error.kind(), io::ErrorKind::NotFound);
+        assert_eq!(io_error.to_string(), "failed to read config");
+        assert_eq!(io_error.source().unwrap().to_string(), "oh no!");
     }
 
+    #[cfg(feature = "std")]
     #[test]
-    fn error_autotraits() {
-        fn assert<E: Unpin + Send + Sync + 'static>() {}
-        assert::<Error>();
+    fn into_io_error_generic() {
+        let error = anyhow!("oh no!");
+
+        let io_error = io::Error::from(error);
+        assert_eq!(io_error.kind(), io::ErrorKind::Other);
+        assert_eq!(io_error.to_string(), "oh no!");
     }
 
+    #[cfg(feature = "std")]
     #[test]
-    fn drop_works() {
-        #[derive(Debug)]
-        struct DetectDrop {
-            has_dropped: Arc<AtomicBool>,
+    fn debug_indent() {
+        let error = anyhow!("oh no!")
+            .context("failed to read config")
+            .context("failed to start");
+
+        let expected = "\
+failed to start
+
+Caused by:
+        0: failed to read config
+        1: oh no!
+";
+        assert!(format!("{:8?}", error).starts_with(expected));
+
+        let expected = "\
+failed to start
+
+Caused by:
+    0: failed to read config
+    1: oh no!
+";
+        assert!(format!("{:?}", error).starts_with(expected));
+    }
+
+    #[test]
+    fn debug_show_types() {
+        use core::any::type_name;
+
+        // The setting made by crate::set_show_types is global, so leave it
+        // alone for the sake of tests running concurrently.
+        struct Typed<'a>(&'a Error);
+
+        impl Display for Typed<'_> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                self.0.debug_with_labels(f, &Labels::default(), true, true)
+            }
         }
 
-        impl StdError for DetectDrop {}
+        #[derive(Debug)]
+        struct Outer(io::Error);
 
-        impl Display for DetectDrop {
+        impl Display for Outer {
             fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-                write!(f, "does something")
+                f.write_str("outer")
             }
         }
 
-        impl Drop for DetectDrop {
-            fn drop(&mut self) {
-                let already_dropped = self.has_dropped.swap(true, SeqCst);
-                assert!(!already_dropped);
+        impl StdError for Outer {
+            fn source(&self) -> Option<&(dyn StdError + 'static)> {
+                Some(&self.0)
             }
         }
 
-        let has_dropped = Arc::new(AtomicBool::new(false));
+        let io_error = type_name::<io::Error>();
+        let error = Error::new(io::Error::new(io::ErrorKind::NotFound, "oh no!"))
+            .context("failed to read config")
+            .context(String::from("failed to start"));
+        let expected = format!(
+            "\
+[alloc::string::String] failed to start
 
-        drop(Error::from(DetectDrop {
-            has_dropped: has_dropped.clone(),
-        }));
+Caused by:
+    0: [&str] failed to read config
+    1: [{}] oh no!
+",
+            io_error,
+        );
+        assert!(Typed(&error).to_string().starts_with(&expected));
 
-        assert!(has_dropped.load(SeqCst));
+        let result: Result<(), io::Error> = Err(io::Error::new(io::ErrorKind::NotFound, "oh no!"));
+        let error = result.context("failed to read config").unwrap_err();
+        let expected = format!(
+            "[&str] failed to read config\n\nCaused by:\n    [{}] oh no!\n",
+            io_error
+        );
+        assert!(Typed(&error).to_string().starts_with(&expected));
+
+        // The source of Outer is not known to be an io::Error.
+        let error = Error::new(Outer(io::Error::new(io::ErrorKind::NotFound, "oh no!")));
+        let expected = format!(
+            "[{}] outer\n\nCaused by:\n    oh no!\n",
+            type_name::<Outer>()
+        );
+        assert!(Typed(&error).to_string().starts_with(&expected));
+
+        assert!(format!("{:?}", anyhow!("oh no!")).starts_with("oh no!\n"));
+    }
+
+    #[test]
+    fn debug_with_labels() {
+        struct Localized<'a>(&'a Error, Labels);
+
+        impl Display for Localized<'_> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                self.0.debug_with_labels(f, &self.1, false, false)
+            }
+        }
+
+        // Labels set by crate::set_labels are global, so leave them alone for
+        // the sake of tests running concurrently and pass them explicitly. The
+        // same goes for the backtrace hint, which is turned off here.
+        let labels = Labels {
+            caused_by: "Causé par :",
+            see: "Voir :",
+            ..Labels::default()
+        };
+        let error = Error::builder()
+            .message("disque plein")
+            .capture_backtrace(false)
+            .build()
+            .context("échec de l'enregistrement")
+            .with_url("https://example.com/disque");
+        let expected = "\
+échec de l'enregistrement
+
+Causé par :
+    disque plein
+
+Voir : https://example.com/disque
+";
+        assert_eq!(Localized(&error, labels).to_string(), expected);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn catch_unwind_ok() {
+        let value = crate::catch_unwind(|| 1 + 1).unwrap();
+        assert_eq!(value, 2);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn catch_unwind_panic() {
+        let error = crate::catch_unwind(|| -> i32 { panic!("boom") }).unwrap_err();
+        assert_eq!(error.to_string(), "boom");
     }
 }
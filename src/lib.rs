@@ -161,6 +161,23 @@
 //!
 //! <br>
 //!
+//! # No-std support
+//!
+//! In no_std mode, the same API is almost all available and works the same
+//! way. To depend on Anyhow in no_std mode, disable our default enabled "std"
+//! feature in Cargo.toml. A global allocator is required.
+//!
+//! ```toml
+//! [dependencies]
+//! anyhow = { version = "1.0", default-features = false }
+//! ```
+//!
+//! Without the standard library, errors are built on `core::error::Error`,
+//! no backtraces are captured, and [`Report`], [`catch_unwind`] and the
+//! conversion into `std::io::Error` are not available.
+//!
+//! <br>
+//!
 //! # Acknowledgements
 //!
 //! The implementation of the `anyhow::Error` type is forked from
@@ -172,22 +189,63 @@
 //! [fehler]: https://github.com/withoutboats/fehler
 
 #![doc(html_root_url = "https://docs.rs/anyhow/1.0.0")]
-#![cfg_attr(backtrace, feature(backtrace))]
+#![cfg_attr(backtrace, feature(error_generic_member_access))]
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 
+extern crate alloc;
+
+#[cfg(backtrace)]
+mod backtrace;
+mod builder;
 mod context;
+mod encode;
 mod error;
+pub mod prelude;
+#[cfg(feature = "std")]
+mod report;
+#[cfg(feature = "serde")]
+mod ser;
+mod template;
+
+#[cfg(backtrace)]
+pub use crate::backtrace::BacktraceFrame;
+pub use crate::builder::ErrorBuilder;
+pub use crate::context::{Context, ContextLocal};
+pub use crate::encode::DecodeError;
+pub use crate::error::{Chain, ChainMut, Error};
+#[cfg(feature = "std")]
+pub use crate::report::Report;
+pub use crate::template::ErrorTemplate;
 
-#[cfg(not(feature = "std"))]
-compile_error!("no_std support is not implemented yet");
+#[cfg(backtrace)]
+use core::mem;
+#[cfg(feature = "std")]
+use core::ptr;
+#[cfg(feature = "std")]
+use core::sync::atomic::AtomicPtr;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
-pub use crate::context::Context;
-pub use crate::error::{Chain, Error};
+#[cfg(feature = "std")]
+use alloc::boxed::Box;
+#[cfg(feature = "std")]
+use alloc::sync::Arc;
+#[cfg(feature = "std")]
+use core::error::Error as StdError;
+#[cfg(feature = "std")]
+use core::fmt;
+#[cfg(feature = "std")]
+use std::sync::{PoisonError, RwLock};
 
 /// `Result<T, Error>`
 ///
 /// This is a reasonable return type to use throughout your application but also
 /// for `fn main`; if you do, failures will be printed along with any
-/// [context][Context] and a backtrace if one was captured.
+/// [context][Context] and a backtrace if one was captured. For control over
+/// the exit code, `fn main` can return a [`Report`] instead.
+///
+/// The error type defaults to [`Error`] but can be overridden, so that a glob
+/// import of this alias does not get in the way of the occasional function
+/// that returns some other error type, such as `Result<T, fmt::Error>`.
 ///
 /// # Example
 ///
@@ -218,12 +276,410 @@ pub use crate::error::{Chain, Error};
 ///     Ok(())
 /// }
 /// ```
-pub type Result<T> = std::result::Result<T, Error>;
+pub type Result<T, E = Error> = core::result::Result<T, E>;
+
+/// How serious an error is, for routing it in logs.
+///
+/// Set with [`Error::with_severity`] and read with [`Error::severity`]. The
+/// [`Default`] is `Error`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Severity {
+    /// A failure. This is the severity of errors that did not set one.
+    #[default]
+    Error,
+    /// Something that went wrong but was recovered from.
+    Warning,
+    /// An expected outcome that is reported through an error.
+    Info,
+}
+
+/// The process and build in which an error was created.
+///
+/// Returned by [`Error::origin`], which is only available if the `build-info`
+/// Cargo feature is enabled.
+#[cfg(feature = "build-info")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Origin {
+    /// The ID of the process, as returned by [`std::process::id`].
+    pub pid: u32,
+    /// The version of the `anyhow` crate, from its `CARGO_PKG_VERSION`.
+    ///
+    /// This is not the version of the crate that created the error. The
+    /// version is read by anyhow where the error is constructed, and the
+    /// errors created by `Error::new`, `?` and `context` are constructed
+    /// inside anyhow rather than in code expanded into the calling crate.
+    pub version: &'static str,
+}
+
+/// Invoke a closure, converting a panic into an `Error`.
+///
+/// Requires the `std` feature.
+///
+/// This is a wrapper around [`std::panic::catch_unwind`] in which a caught
+/// panic becomes an error via [`Error::from_panic`]. If the closure returns
+/// normally, its value is passed through unchanged and no backtrace is
+/// captured.
+///
+/// # Example
+///
+/// ```
+/// # fn untrusted_plugin() -> u32 {
+/// #     0
+/// # }
+/// #
+/// use anyhow::Result;
+///
+/// fn run_plugin() -> Result<u32> {
+///     anyhow::catch_unwind(untrusted_plugin)
+/// }
+/// ```
+#[cfg(feature = "std")]
+pub fn catch_unwind<F, R>(f: F) -> Result<R>
+where
+    F: FnOnce() -> R + std::panic::UnwindSafe,
+{
+    std::panic::catch_unwind(f).map_err(Error::from_panic)
+}
+
+// usize::MAX stands for no limit.
+pub(crate) static BACKTRACE_FRAME_LIMIT: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// Limit the number of backtrace frames printed by the `Debug` representation
+/// of [`Error`].
+///
+/// With a limit of `Some(n)`, only the first `n` frames are printed, followed
+/// by a line saying how many frames were left out. `None` prints every frame,
+/// which is the default. The limit applies to every `Error` in the process.
+///
+/// This only affects rendering. Backtraces are captured in full, and
+/// `Error::backtrace` always returns the whole backtrace.
+///
+/// # Example
+///
+/// ```
+/// // Keep error logs short; the full backtrace is still available through
+/// // Error::backtrace.
+/// anyhow::set_backtrace_frame_limit(Some(20));
+/// ```
+pub fn set_backtrace_frame_limit(limit: Option<usize>) {
+    let limit = limit.unwrap_or(usize::MAX);
+    BACKTRACE_FRAME_LIMIT.store(limit, Ordering::Relaxed);
+}
+
+/// Whether the `Debug` representation of [`Error`] resolves a backtrace to
+/// function names and source locations.
+///
+/// Set with [`set_backtrace_resolution`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resolution {
+    /// Resolve the backtrace. This is the default.
+    Full,
+    /// Print a line saying that a backtrace was captured instead of the
+    /// backtrace itself.
+    Never,
+}
+
+pub(crate) static RESOLVE_BACKTRACE: AtomicBool = AtomicBool::new(true);
+
+/// Choose whether the `Debug` representation of [`Error`] resolves backtraces.
+///
+/// Backtraces are captured unresolved, which is cheap, and resolving them to
+/// function names and source locations is what makes printing one
+/// expensive. With [`Resolution::Never`] the backtrace is not resolved at
+/// all when the error is printed. The standard library resolves a backtrace
+/// as a whole the first time it is formatted, with no way to resolve only
+/// some of its frames, so there is no setting in between; to shorten the
+/// output instead, see [`set_backtrace_frame_limit`].
+///
+/// Like `set_backtrace_frame_limit`, it only affects rendering and applies
+/// to every `Error` in the process. `Error::backtrace` returns the backtrace
+/// as captured, to be formatted however the caller chooses.
+///
+/// # Example
+///
+/// ```
+/// use anyhow::Resolution;
+///
+/// // Errors are logged often and their backtraces are rarely looked at.
+/// anyhow::set_backtrace_resolution(Resolution::Never);
+/// ```
+pub fn set_backtrace_resolution(resolution: Resolution) {
+    let resolve = resolution == Resolution::Full;
+    RESOLVE_BACKTRACE.store(resolve, Ordering::Relaxed);
+}
+
+pub(crate) static TRIM_BACKTRACE: AtomicBool = AtomicBool::new(true);
+
+/// Choose whether the `Debug` representation of [`Error`] leaves out the
+/// frames at the top of the backtrace that belong to capturing it.
+///
+/// Those are the frames of the standard library's backtrace machinery and of
+/// anyhow itself constructing the error, so that the first frame printed is
+/// the code that created the error. Trimming is on by default.
+///
+/// Like [`set_backtrace_frame_limit`], this only affects rendering and
+/// applies to every `Error` in the process.
+pub fn set_trim_backtrace(trim: bool) {
+    TRIM_BACKTRACE.store(trim, Ordering::Relaxed);
+}
+
+pub(crate) static BACKTRACE_HINT: AtomicBool = AtomicBool::new(true);
+
+/// Choose whether the `Debug` representation of [`Error`] says how to enable
+/// backtraces when they are disabled.
+///
+/// By default a line suggesting the `RUST_LIB_BACKTRACE=1` environment
+/// variable is printed in place of the backtrace of every error created
+/// while backtraces were disabled. With the hint turned off, nothing is
+/// printed in its place, which keeps it from repeating in production logs.
+///
+/// Like [`set_backtrace_frame_limit`], this only affects rendering and
+/// applies to every `Error` in the process.
+pub fn set_backtrace_hint(show: bool) {
+    BACKTRACE_HINT.store(show, Ordering::Relaxed);
+}
+
+pub(crate) static SHOW_TYPES: AtomicBool = AtomicBool::new(false);
+
+/// Choose whether the `Debug` representation of [`Error`] shows the Rust
+/// type of each error in the chain, for debugging.
+///
+/// When enabled, each error is preceded by the name of its type in square
+/// brackets, as given by [`core::any::type_name`]:
+///
+/// ```console
+/// [&str] failed to read config
+///
+/// Caused by:
+///     [std::io::error::Error] No such file or directory (os error 2)
+/// ```
+///
+/// For a layer of [context][Error::context] the type shown is that of the
+/// context. Only the types that anyhow itself knows of can be shown: those of
+/// the errors passed to it and of the context attached to them. The sources
+/// of those errors, and errors attached by [`Error::wrap_source`], are
+/// trait objects whose type is unknown, and are shown without a type.
+///
+/// This is off by default. Like [`set_backtrace_frame_limit`], it only
+/// affects rendering and applies to every `Error` in the process.
+pub fn set_show_types(show: bool) {
+    SHOW_TYPES.store(show, Ordering::Relaxed);
+}
+
+/// Text of the headings in the `Debug` representation of [`Error`], for
+/// translating it into languages other than English.
+///
+/// Each label is printed as is, so it includes any punctuation the language
+/// calls for. The [`Default`] is the English text.
+#[derive(Clone, Copy, Debug)]
+pub struct Labels {
+    /// Heading above the list of causes. Defaults to `"Caused by:"`.
+    pub caused_by: &'static str,
+    /// Heading above the errors grouped by [`Error::from_errors`]. Defaults to
+    /// `"Errors:"`.
+    pub errors: &'static str,
+    /// Heading above the errors attached by [`Error::add_related`]. Defaults
+    /// to `"Additionally:"`.
+    pub additionally: &'static str,
+    /// Prefix of each note attached by [`Error::note`]. Defaults to
+    /// `"Note:"`.
+    pub note: &'static str,
+    /// Prefix of the URL attached by [`Error::with_url`]. Defaults to
+    /// `"See:"`.
+    pub see: &'static str,
+    /// Line printed in place of the backtrace when backtraces are disabled,
+    /// unless turned off by [`set_backtrace_hint`]. Defaults to a hint to set
+    /// the `RUST_LIB_BACKTRACE` environment variable.
+    ///
+    /// Only used on toolchains where anyhow captures backtraces.
+    pub backtrace_disabled: &'static str,
+    /// Line printed in place of the backtrace when one was captured but
+    /// [`Resolution::Never`] is set. Defaults to `"Backtrace captured but not
+    /// resolved"`.
+    ///
+    /// Only used on toolchains where anyhow captures backtraces.
+    pub backtrace_unresolved: &'static str,
+    /// Line printed in place of the backtrace when the `RUST_LIB_BACKTRACE`
+    /// environment variable asks for one but anyhow cannot capture
+    /// backtraces. Defaults to `"Backtraces are not available on this Rust
+    /// toolchain"`.
+    ///
+    /// Only used on toolchains where anyhow does not capture backtraces, with
+    /// the `std` feature.
+    pub backtrace_unsupported: &'static str,
+    /// Heading above the span in which the error was created. Defaults to
+    /// `"Span trace:"`.
+    ///
+    /// Only used with the `tracing` feature.
+    pub span_trace: &'static str,
+}
+
+impl Labels {
+    pub(crate) const ENGLISH: Labels = Labels {
+        caused_by: "Caused by:",
+        errors: "Errors:",
+        additionally: "Additionally:",
+        note: "Note:",
+        see: "See:",
+        backtrace_disabled: "Backtrace disabled; run with RUST_LIB_BACKTRACE=1 environment variable to display a backtrace",
+        backtrace_unresolved: "Backtrace captured but not resolved",
+        backtrace_unsupported: "Backtraces are not available on this Rust toolchain",
+        span_trace: "Span trace:",
+    };
+}
+
+impl Default for Labels {
+    fn default() -> Self {
+        Labels::ENGLISH
+    }
+}
+
+#[cfg(feature = "std")]
+pub(crate) static LABELS: RwLock<Labels> = RwLock::new(Labels::ENGLISH);
+
+/// Replace the headings used by the `Debug` representation of [`Error`].
+///
+/// Like [`set_backtrace_frame_limit`], this only affects rendering and
+/// applies to every `Error` in the process. A hook installed by [`set_hook`]
+/// renders errors its own way and does not use the labels.
+///
+/// Requires the `std` feature.
+///
+/// # Example
+///
+/// ```
+/// use anyhow::{anyhow, Labels};
+///
+/// anyhow::set_labels(Labels {
+///     caused_by: "Causé par :",
+///     ..Labels::default()
+/// });
+///
+/// let error = anyhow!("disque plein").context("échec de l'enregistrement");
+/// assert!(format!("{:?}", error).contains("\nCausé par :\n    disque plein\n"));
+/// ```
+#[cfg(feature = "std")]
+pub fn set_labels(labels: Labels) {
+    *LABELS.write().unwrap_or_else(PoisonError::into_inner) = labels;
+}
+
+#[cfg(feature = "std")]
+type Hook = dyn Fn(&Error, &mut fmt::Formatter) -> fmt::Result + Send + Sync;
+
+// The hook is cloned out of the lock before it is called, so that it can
+// format other errors, which reads the lock again, or replace itself.
+#[cfg(feature = "std")]
+pub(crate) static HOOK: RwLock<Option<Arc<Hook>>> = RwLock::new(None);
+
+/// Install a function that renders the `Debug` representation of every
+/// [`Error`], replacing the built-in one.
+///
+/// This is analogous to [`std::panic::set_hook`]: a program can set a single
+/// formatting policy once at startup, for example to add colors or render
+/// notes differently, and it is used wherever an error is formatted with
+/// `{:?}`, including by [`Report`]. The hook receives the full `Error`, so it
+/// has access to its [chain][Error::chain], backtrace and everything else
+/// attached to it. Installing a hook replaces any previous
+/// one.
+///
+/// The hook may be called from any thread, possibly from several at once,
+/// hence the `Send` and `Sync` bounds. No lock is held while it runs, so it
+/// is free to format other errors with `{:?}`, such as the entries of
+/// [`related()`][Error::related], which calls the hook again for each of
+/// them. It must not format the error it is given with `{:?}`, which would
+/// recurse forever; [`Display`][core::fmt::Display] and the accessors of
+/// `Error` are fine. A hook that calls `set_hook` replaces itself for later
+/// calls, while the call in progress completes with the old hook.
+///
+/// Requires the `std` feature.
+///
+/// # Example
+///
+/// ```
+/// use anyhow::anyhow;
+///
+/// anyhow::set_hook(Box::new(|error, f| {
+///     write!(f, "error: {}", error)?;
+///     for cause in error.chain().skip(1) {
+///         write!(f, "\n  because: {}", cause)?;
+///     }
+///     Ok(())
+/// }));
+///
+/// let error = anyhow!("disk full").context("failed to save");
+/// assert_eq!(format!("{:?}", error), "error: failed to save\n  because: disk full");
+/// ```
+#[cfg(feature = "std")]
+pub fn set_hook(hook: Box<Hook>) {
+    *HOOK.write().unwrap_or_else(PoisonError::into_inner) = Some(Arc::from(hook));
+}
+
+#[cfg(feature = "std")]
+type BacktraceFilter = fn(&(dyn StdError + 'static)) -> bool;
+
+// The filter installed by set_backtrace_filter, or null. Every new error looks
+// at it, so it is a plain atomic rather than behind a lock.
+#[cfg(feature = "std")]
+static BACKTRACE_FILTER: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+
+#[cfg(backtrace)]
+pub(crate) fn backtrace_filter() -> Option<BacktraceFilter> {
+    let filter = BACKTRACE_FILTER.load(Ordering::Acquire);
+    if filter.is_null() {
+        None
+    } else {
+        // Only ever set from a BacktraceFilter by set_backtrace_filter.
+        Some(unsafe { mem::transmute::<*mut (), BacktraceFilter>(filter) })
+    }
+}
+
+/// Install a predicate deciding which errors have a backtrace captured.
+///
+/// Capturing a backtrace is comparatively expensive. Where an error type is
+/// created often and its backtrace is never looked at, for example an
+/// expected "not found" that is handled right away, the filter can return
+/// `false` for it to skip the capture. The error then holds a disabled
+/// backtrace, as if `RUST_LIB_BACKTRACE=0` were set. When the filter returns
+/// `true`, the usual `RUST_BACKTRACE` and `RUST_LIB_BACKTRACE` settings still
+/// apply. Installing a filter replaces any previous one.
+///
+/// The filter is consulted by [`Error::new`] and everything built on it,
+/// including the conversion by `?`. Errors created from a message, by the
+/// [`anyhow!`] and [`bail!`] macros or by [`Error::msg`], are subject to it
+/// too: the filter is given an error whose `Display` is the message, so it
+/// may look at the message but cannot downcast it to the message type. An
+/// error that provides its own backtrace keeps it, without the filter being
+/// called.
+///
+/// The filter is called for every new error that would capture a backtrace,
+/// so it should be cheap. Backtraces are only ever captured on toolchains that
+/// support them, so on others the filter is never called.
+///
+/// Requires the `std` feature.
+///
+/// # Example
+///
+/// ```
+/// use std::io;
+///
+/// // Never capture a backtrace for I/O errors.
+/// anyhow::set_backtrace_filter(|error| !error.is::<io::Error>());
+/// ```
+#[cfg(feature = "std")]
+pub fn set_backtrace_filter(filter: BacktraceFilter) {
+    BACKTRACE_FILTER.store(filter as *mut (), Ordering::Release);
+}
 
 /// Return early with an error.
 ///
 /// This macro is equivalent to `return Err(From::from($err))`.
 ///
+/// The function need not return [`anyhow::Result`][Result]. A message is
+/// made into an [`Error`] first, so for a message the function's error type
+/// must implement `From<anyhow::Error>`; for an error value it must
+/// implement `From` that value's type. The same goes for [`ensure!`] and the
+/// macros built on it.
+///
 /// # Example
 ///
 /// ```
@@ -283,12 +739,290 @@ pub type Result<T> = std::result::Result<T, Error>;
 /// ```
 #[macro_export]
 macro_rules! bail {
+    ($msg:literal $(,)?) => {
+        return $crate::private::Err($crate::private::From::from($crate::anyhow!($msg)));
+    };
+    ($err:expr $(,)?) => {
+        return $crate::private::Err($crate::private::From::from($err));
+    };
+    ($fmt:expr, $($arg:tt)*) => {
+        return $crate::private::Err($crate::private::From::from(
+            $crate::anyhow!($fmt, $($arg)*),
+        ));
+    };
+}
+
+/// Return early with an error that has no backtrace.
+///
+/// This is [`bail!`] for errors that are part of the expected control flow,
+/// such as the rejection of invalid input, and are handled by the caller
+/// rather than reported. Capturing a backtrace is the most expensive part of
+/// creating an `Error` when backtraces are enabled, and it is wasted on such
+/// errors. The backtrace of the returned error is reported as disabled
+/// regardless of the `RUST_LIB_BACKTRACE` and `RUST_BACKTRACE` environment
+/// variables, unless an error value passed in provides a backtrace of its own.
+///
+/// It takes either a string literal, a format string with arguments, or an
+/// error value that implements `std::error::Error`. Like [`Error::new`], an
+/// error value keeps its own sources as part of the chain and can be
+/// recovered by downcasting.
+///
+/// # Example
+///
+/// ```
+/// # use anyhow::{bail_quiet, Result};
+/// #
+/// fn parse_level(s: &str) -> Result<u8> {
+///     match s {
+///         "low" => Ok(1),
+///         "high" => Ok(2),
+///         _ => bail_quiet!("unknown level {:?}", s),
+///     }
+/// }
+/// #
+/// # fn main() {
+/// #     assert!(parse_level("medium").is_err());
+/// # }
+/// ```
+#[macro_export]
+macro_rules! bail_quiet {
+    ($msg:literal $(,)?) => {
+        return $crate::private::Err($crate::private::From::from(
+            $crate::private::new_adhoc_quiet($msg),
+        ))
+    };
     ($err:expr $(,)?) => {
-        return std::result::Result::Err(std::convert::From::from($err));
+        return $crate::private::Err($crate::private::From::from(
+            $crate::private::new_quiet($err),
+        ))
     };
     ($fmt:expr, $($arg:tt)*) => {
-        return std::result::Result::Err($crate::anyhow!($fmt, $($arg)*));
+        return $crate::private::Err($crate::private::From::from(
+            $crate::private::new_adhoc_quiet($crate::private::format!($fmt, $($arg)*)),
+        ))
+    };
+}
+
+/// Return early with an error if a condition is not satisfied.
+///
+/// This macro is equivalent to `if !$cond { return Err(From::from($err)); }`.
+///
+/// Analogously to `assert!`, `ensure!` takes a condition and exits the function
+/// if the condition fails. Unlike `assert!`, `ensure!` returns an `Error`
+/// rather than panicking.
+///
+/// After the condition comes either a message, in the form of a string literal
+/// optionally followed by format arguments, or an error value of any type that
+/// converts into `Error`. The error value is passed through `From::from`
+/// unchanged, so it can be recovered later by downcasting.
+///
+/// As with [`bail!`], the function may return a `Result` with an error type
+/// of its own. That type must implement `From<anyhow::Error>` if a message is
+/// given, or `From` the type of the error value otherwise.
+///
+/// # Example
+///
+/// ```
+/// # use anyhow::{ensure, Result};
+/// #
+/// # fn main() -> Result<()> {
+/// #     let user = 0;
+/// #
+/// ensure!(user == 0, "only user 0 is allowed");
+/// #     Ok(())
+/// # }
+/// ```
+///
+/// ```
+/// # use anyhow::{ensure, Result};
+/// # use std::fmt::{self, Display};
+/// #
+/// # #[derive(Debug)]
+/// # enum ScienceError {
+/// #     RecursionLimitExceeded,
+/// # }
+/// #
+/// # impl std::error::Error for ScienceError {}
+/// #
+/// # impl Display for ScienceError {
+/// #     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+/// #         unimplemented!()
+/// #     }
+/// # }
+/// #
+/// # const MAX_DEPTH: usize = 1;
+/// #
+/// # const IGNORE: &str = stringify! {
+/// #[derive(Error, Debug)]
+/// enum ScienceError {
+///     #[error(display = "recursion limit exceeded")]
+///     RecursionLimitExceeded,
+///     ...
+/// }
+/// # };
+///
+/// # fn main() -> Result<()> {
+/// #     let depth = 0;
+/// #
+/// ensure!(depth <= MAX_DEPTH, ScienceError::RecursionLimitExceeded);
+/// #     Ok(())
+/// # }
+/// ```
+#[macro_export]
+macro_rules! ensure {
+    ($cond:expr, $msg:literal $(,)?) => {
+        if !$cond {
+            return $crate::private::Err($crate::private::From::from($crate::anyhow!($msg)));
+        }
+    };
+    ($cond:expr, $err:expr $(,)?) => {
+        if !$cond {
+            return $crate::private::Err($crate::private::From::from($err));
+        }
+    };
+    ($cond:expr, $fmt:expr, $($arg:tt)*) => {
+        if !$cond {
+            return $crate::private::Err($crate::private::From::from(
+                $crate::anyhow!($fmt, $($arg)*),
+            ));
+        }
+    };
+}
+
+/// Return early with an error if two expressions are not equal to each other.
+///
+/// This macro is the `ensure!` counterpart of `assert_eq!`. On failure, the
+/// error message shows the `Debug` representation of both values. A custom
+/// message can be given after the two expressions, in the same form as the
+/// arguments of `anyhow!`.
+///
+/// # Example
+///
+/// ```
+/// # use anyhow::{ensure_eq, Result};
+/// #
+/// # fn main() -> Result<()> {
+/// #     let header = [0x7f, b'E', b'L', b'F'];
+/// #
+/// ensure_eq!(header, [0x7f, b'E', b'L', b'F'], "not an ELF file");
+/// #     Ok(())
+/// # }
+/// ```
+#[macro_export]
+macro_rules! ensure_eq {
+    ($left:expr, $right:expr $(,)?) => {
+        match (&$left, &$right) {
+            (left_val, right_val) => {
+                if !(*left_val == *right_val) {
+                    $crate::bail!(
+                        "assertion failed: `(left == right)`\n  left: `{:?}`,\n right: `{:?}`",
+                        &*left_val,
+                        &*right_val,
+                    );
+                }
+            }
+        }
+    };
+    ($left:expr, $right:expr, $($arg:tt)+) => {
+        match (&$left, &$right) {
+            (left_val, right_val) => {
+                if !(*left_val == *right_val) {
+                    $crate::bail!(
+                        "assertion failed: `(left == right)`\n  left: `{:?}`,\n right: `{:?}`: {}",
+                        &*left_val,
+                        &*right_val,
+                        format_args!($($arg)+),
+                    );
+                }
+            }
+        }
+    };
+}
+
+/// Return early with an error if two expressions are equal to each other.
+///
+/// This macro is the `ensure!` counterpart of `assert_ne!`. On failure, the
+/// error message shows the `Debug` representation of both values. A custom
+/// message can be given after the two expressions, in the same form as the
+/// arguments of `anyhow!`.
+///
+/// # Example
+///
+/// ```
+/// # use anyhow::{ensure_ne, Result};
+/// #
+/// # fn main() -> Result<()> {
+/// #     let (src, dst) = ("a.txt", "b.txt");
+/// #
+/// ensure_ne!(src, dst, "cannot copy {} onto itself", src);
+/// #     Ok(())
+/// # }
+/// ```
+#[macro_export]
+macro_rules! ensure_ne {
+    ($left:expr, $right:expr $(,)?) => {
+        match (&$left, &$right) {
+            (left_val, right_val) => {
+                if *left_val == *right_val {
+                    $crate::bail!(
+                        "assertion failed: `(left != right)`\n  left: `{:?}`,\n right: `{:?}`",
+                        &*left_val,
+                        &*right_val,
+                    );
+                }
+            }
+        }
     };
+    ($left:expr, $right:expr, $($arg:tt)+) => {
+        match (&$left, &$right) {
+            (left_val, right_val) => {
+                if *left_val == *right_val {
+                    $crate::bail!(
+                        "assertion failed: `(left != right)`\n  left: `{:?}`,\n right: `{:?}`: {}",
+                        &*left_val,
+                        &*right_val,
+                        format_args!($($arg)+),
+                    );
+                }
+            }
+        }
+    };
+}
+
+/// Dispatch on the concrete type of an error.
+///
+/// Each arm names a binding and a type. The arms are tried in order against
+/// the outermost error using [`Error::downcast_ref`], and the first one whose
+/// type matches is evaluated with the binding referring to the downcast
+/// error. If no type matches, the final `_` arm is evaluated.
+///
+/// # Example
+///
+/// ```
+/// use anyhow::{downcast_matches, Error};
+/// use std::io;
+/// use std::num::ParseIntError;
+///
+/// fn exit_status(error: &Error) -> i32 {
+///     downcast_matches!(error,
+///         e: io::Error => if e.kind() == io::ErrorKind::NotFound { 2 } else { 3 },
+///         _e: ParseIntError => 4,
+///         _ => 1,
+///     )
+/// }
+/// ```
+#[macro_export]
+macro_rules! downcast_matches {
+    ($error:expr, $($binding:ident : $ty:ty => $arm:expr,)* _ => $default:expr $(,)?) => {{
+        let error: &$crate::Error = &$error;
+        $(
+            if let $crate::private::Some($binding) = error.downcast_ref::<$ty>() {
+                $arm
+            } else
+        )* {
+            $default
+        }
+    }};
 }
 
 /// Construct an ad-hoc error from a string.
@@ -313,13 +1047,69 @@ macro_rules! bail {
 ///     # Ok(())
 /// }
 /// ```
+///
+/// # Source error
+///
+/// The message, or format string with arguments, may be followed by a
+/// semicolon and an error value, which becomes the `source()` of the new
+/// error. This builds a chain of two links in one expression, with the
+/// message on top and the given error and its own sources below:
+///
+/// ```
+/// use anyhow::anyhow;
+/// use std::io;
+///
+/// let io_error = io::Error::new(io::ErrorKind::NotFound, "config.toml not found");
+/// let error = anyhow!("failed to start server on port {}", 8080; io_error);
+///
+/// assert!(error.chain_eq(&[
+///     "failed to start server on port 8080",
+///     "config.toml not found",
+/// ]));
+/// ```
+///
+/// The result is the same as that of [`Context::context`] on an `Err`
+/// holding the source error, and the message is a layer of context in the
+/// same sense. The difference is in how it reads at the call site: a new,
+/// higher level error is created there and names its cause, rather than an
+/// existing error being passed up and annotated. The source must implement
+/// `std::error::Error`; to put a message on top of an `Error`, use
+/// [`Error::context`].
 #[macro_export]
 macro_rules! anyhow {
+    ($msg:expr; $source:expr $(,)?) => {
+        $crate::private::new_adhoc_with_source($msg, $source)
+    };
+    ($fmt:expr, $($arg:expr),+ $(,)?; $source:expr $(,)?) => {
+        $crate::private::new_adhoc_with_source(
+            $crate::private::format!($fmt, $($arg),+),
+            $source,
+        )
+    };
     ($msg:expr $(,)?) => {
         $crate::private::new_adhoc($msg)
     };
     ($fmt:expr, $($arg:tt)*) => {
-        $crate::private::new_adhoc(format!($fmt, $($arg)*))
+        $crate::private::new_adhoc($crate::private::format!($fmt, $($arg)*))
+    };
+}
+
+/// Construct an ad-hoc error from a string.
+///
+/// This is an alias of [`anyhow!`] under the name used by the `failure`
+/// crate, for codebases migrating from it.
+///
+/// ```
+/// use anyhow::{format_err, Result};
+///
+/// fn parse_port(s: &str) -> Result<u16> {
+///     s.parse().map_err(|_| format_err!("invalid port {:?}", s))
+/// }
+/// ```
+#[macro_export]
+macro_rules! format_err {
+    ($($arg:tt)*) => {
+        $crate::anyhow!($($arg)*)
     };
 }
 
@@ -327,21 +1117,330 @@ macro_rules! anyhow {
 #[doc(hidden)]
 pub mod private {
     use crate::Error;
-    use std::fmt::{Debug, Display};
+    use core::error::Error as StdError;
+    use core::fmt::{Debug, Display};
+
+    pub use alloc::format;
+    pub use core::convert::From;
+    pub use core::option::Option::Some;
+    pub use core::result::Result::Err;
 
     #[cfg(backtrace)]
     use std::backtrace::Backtrace;
 
     pub fn new_adhoc<M>(message: M) -> Error
+    where
+        M: Display + Debug + Send + Sync + 'static,
+    {
+        Error::new_adhoc_captured(message)
+    }
+
+    pub fn new_adhoc_quiet<M>(message: M) -> Error
     where
         M: Display + Debug + Send + Sync + 'static,
     {
         #[cfg(backtrace)]
-        let backtrace = Some(Backtrace::capture());
+        let backtrace = Some(Backtrace::disabled());
 
         #[cfg(not(backtrace))]
         let backtrace = None;
 
         Error::new_adhoc(message, backtrace)
     }
+
+    pub fn new_quiet<E>(error: E) -> Error
+    where
+        E: StdError + Send + Sync + 'static,
+    {
+        Error::new_without_backtrace(error)
+    }
+
+    pub fn new_adhoc_with_source<M, E>(message: M, source: E) -> Error
+    where
+        M: Display + Send + Sync + 'static,
+        E: StdError + Send + Sync + 'static,
+    {
+        Error::from_context(message, source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Context, Result};
+    use std::fmt;
+
+    fn compare(a: i32, b: i32) -> Result<()> {
+        ensure_eq!(a, b);
+        Ok(())
+    }
+
+    fn compare_with_message(a: i32, b: i32) -> Result<()> {
+        ensure_eq!(a, b, "mismatch for {}", "key");
+        Ok(())
+    }
+
+    fn distinct(a: i32, b: i32) -> Result<()> {
+        ensure_ne!(a, b);
+        Ok(())
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct NonPositive(i32);
+
+    impl fmt::Display for NonPositive {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{} is not positive", self.0)
+        }
+    }
+
+    impl std::error::Error for NonPositive {}
+
+    fn check_message(n: i32) -> Result<()> {
+        ensure!(n > 0, "must be positive");
+        Ok(())
+    }
+
+    fn check_format(n: i32) -> Result<()> {
+        ensure!(n > 0, "{} must be positive", n);
+        Ok(())
+    }
+
+    fn check_error(n: i32) -> Result<()> {
+        ensure!(n > 0, NonPositive(n));
+        Ok(())
+    }
+
+    fn check_quiet(n: i32) -> Result<()> {
+        if n <= 0 {
+            bail_quiet!("{} must be positive", n);
+        }
+        Ok(())
+    }
+
+    #[derive(Debug)]
+    struct AppError(crate::Error);
+
+    impl From<crate::Error> for AppError {
+        fn from(error: crate::Error) -> Self {
+            AppError(error)
+        }
+    }
+
+    fn check_app(n: i32) -> core::result::Result<(), AppError> {
+        ensure!(n != 0, "must not be zero");
+        ensure!(n > 0, "{} must be positive", n);
+        if n > 100 {
+            bail!("must be at most 100");
+        }
+        if n > 10 {
+            bail!("{} must be at most 10", n);
+        }
+        ensure_eq!(n % 2, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn ensure_custom_error() {
+        check_app(1).unwrap();
+        assert_eq!(check_app(0).unwrap_err().0.to_string(), "must not be zero");
+        assert_eq!(
+            check_app(-1).unwrap_err().0.to_string(),
+            "-1 must be positive"
+        );
+        assert_eq!(
+            check_app(101).unwrap_err().0.to_string(),
+            "must be at most 100"
+        );
+        assert_eq!(
+            check_app(11).unwrap_err().0.to_string(),
+            "11 must be at most 10"
+        );
+        let error = check_app(2).unwrap_err().0;
+        assert!(error
+            .to_string()
+            .starts_with("assertion failed: `(left == right)`"));
+    }
+
+    #[test]
+    fn ensure() {
+        check_message(1).unwrap();
+        let error = check_message(0).unwrap_err();
+        assert_eq!(error.to_string(), "must be positive");
+
+        check_format(1).unwrap();
+        let error = check_format(-1).unwrap_err();
+        assert_eq!(error.to_string(), "-1 must be positive");
+
+        check_error(1).unwrap();
+        let error = check_error(-1).unwrap_err();
+        assert_eq!(error.downcast_ref::<NonPositive>(), Some(&NonPositive(-1)));
+    }
+
+    #[test]
+    fn bail_quiet() {
+        check_quiet(1).unwrap();
+        let error = check_quiet(0).unwrap_err();
+        assert_eq!(error.to_string(), "0 must be positive");
+        assert!(!error.has_backtrace());
+        #[cfg(backtrace)]
+        assert_eq!(
+            error.backtrace().status(),
+            std::backtrace::BacktraceStatus::Disabled,
+        );
+
+        let error = (|| -> Result<()> { bail_quiet!(NonPositive(-1)) })().unwrap_err();
+        assert_eq!(error.downcast_ref::<NonPositive>(), Some(&NonPositive(-1)));
+        assert!(!error.has_backtrace());
+
+        #[derive(Debug)]
+        struct Rejected(NonPositive);
+
+        impl fmt::Display for Rejected {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("rejected")
+            }
+        }
+
+        impl std::error::Error for Rejected {
+            fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+                Some(&self.0)
+            }
+        }
+
+        let error = (|| -> Result<()> { bail_quiet!(Rejected(NonPositive(-1))) })().unwrap_err();
+        assert!(error.chain_eq(&["rejected", "-1 is not positive"]));
+        assert!(error.is::<Rejected>());
+        assert!(!error.has_backtrace());
+    }
+
+    #[test]
+    fn anyhow_with_source() {
+        let error = anyhow!("failed to validate"; NonPositive(-1));
+        assert_eq!(error.to_string(), "failed to validate");
+        let source = error.source().unwrap();
+        assert_eq!(source.downcast_ref::<NonPositive>(), Some(&NonPositive(-1)),);
+        assert!(error.chain_eq(&["failed to validate", "-1 is not positive"]));
+
+        let error = anyhow!("failed to validate {}", "input"; NonPositive(0));
+        assert!(error.chain_eq(&["failed to validate input", "0 is not positive"]));
+        assert_eq!(error.root_cause().downcast_ref(), Some(&NonPositive(0)));
+    }
+
+    fn classify(error: &crate::Error) -> String {
+        downcast_matches!(error,
+            e: NonPositive => format!("non-positive {}", e.0),
+            e: std::num::ParseIntError => format!("parse: {}", e),
+            e: fmt::Error => format!("fmt: {}", e),
+            _ => "other".to_owned(),
+        )
+    }
+
+    #[test]
+    fn downcast_matches() {
+        let error = crate::Error::new(NonPositive(-1));
+        assert_eq!(classify(&error), "non-positive -1");
+
+        let error = crate::Error::new("x".parse::<u8>().unwrap_err());
+        assert_eq!(classify(&error), "parse: invalid digit found in string");
+
+        let error = crate::Error::new(fmt::Error);
+        assert_eq!(
+            classify(&error),
+            "fmt: an error occurred when formatting an argument"
+        );
+
+        let error = anyhow!("oh no!");
+        assert_eq!(classify(&error), "other");
+
+        let error = crate::Error::new(NonPositive(0)).context("failed");
+        assert_eq!(classify(&error), "other");
+    }
+
+    #[test]
+    fn format_err() {
+        assert_eq!(format_err!("{}", 5).to_string(), "5");
+        assert_eq!(format_err!("oh no!").to_string(), "oh no!");
+    }
+
+    fn read_config(s: &str) -> Result<u16> {
+        let port = s.parse::<u16>().wrap_err("invalid port")?;
+        let port = check_error(i32::from(port))
+            .map(|()| port)
+            .wrap_err_with(|| format!("port {} is not allowed", port))?;
+        Ok(port)
+    }
+
+    #[test]
+    fn wrap_err() {
+        assert_eq!(read_config("80").unwrap(), 80);
+
+        let error = read_config("x").unwrap_err();
+        assert_eq!(error.to_string(), "invalid port");
+        assert!(error
+            .chain()
+            .nth(1)
+            .unwrap()
+            .is::<std::num::ParseIntError>());
+
+        let error = read_config("0").unwrap_err();
+        assert_eq!(error.to_string(), "port 0 is not allowed");
+        assert_eq!(
+            error.chain().nth(1).unwrap().to_string(),
+            "0 is not positive"
+        );
+    }
+
+    #[test]
+    fn ensure_eq() {
+        compare(1, 1).unwrap();
+
+        let error = compare(1, 2).unwrap_err();
+        let expected = "\
+assertion failed: `(left == right)`
+  left: `1`,
+ right: `2`";
+        assert_eq!(error.to_string(), expected);
+
+        let error = compare_with_message(1, 2).unwrap_err();
+        let expected = "\
+assertion failed: `(left == right)`
+  left: `1`,
+ right: `2`: mismatch for key";
+        assert_eq!(error.to_string(), expected);
+    }
+
+    #[test]
+    fn ensure_ne() {
+        distinct(1, 2).unwrap();
+
+        let error = distinct(1, 1).unwrap_err();
+        let expected = "\
+assertion failed: `(left != right)`
+  left: `1`,
+ right: `1`";
+        assert_eq!(error.to_string(), expected);
+    }
+
+    #[test]
+    fn result_alias() {
+        fn parse(s: &str) -> Result<u32> {
+            let n = s.parse::<u32>()?;
+            Ok(n)
+        }
+
+        assert_eq!(parse("1").unwrap(), 1);
+        assert_eq!(
+            parse("x").unwrap_err().to_string(),
+            "invalid digit found in string"
+        );
+    }
+
+    #[test]
+    fn result_alias_custom_error() {
+        fn write() -> Result<(), fmt::Error> {
+            Err(fmt::Error)
+        }
+
+        assert_eq!(write(), Err(fmt::Error));
+    }
 }
@@ -0,0 +1,26 @@
+//! The items needed by nearly every user of this crate.
+//!
+//! ```
+//! use anyhow::prelude::*;
+//!
+//! fn parse_port(s: &str) -> Result<u16> {
+//!     let port = s.parse::<u16>().context("invalid port number")?;
+//!     ensure!(port != 0, "port must be nonzero");
+//!     Ok(port)
+//! }
+//! #
+//! # fn main() {
+//! #     assert_eq!(parse_port("80").unwrap(), 80);
+//! #     assert!(parse_port("0").is_err());
+//! # }
+//! ```
+//!
+//! The prelude contains [`Error`], [`Result`], the [`Context`] trait, and the
+//! [`anyhow!`], [`bail!`] and [`ensure!`] macros. Everything here is also
+//! exported from the crate root.
+//!
+//! Types that most code only encounters as the return value of a method, such
+//! as [`Chain`][crate::Chain], are left out so that a glob import of the
+//! prelude brings in as few names as possible.
+
+pub use crate::{anyhow, bail, ensure, Context, Error, Result};
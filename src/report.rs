@@ -0,0 +1,142 @@
+use crate::Error;
+use std::fmt::{self, Debug, Display};
+use std::process::{ExitCode, Termination};
+
+/// Return type for `fn main` that reports errors the way anyhow formats them.
+///
+/// Returning `anyhow::Result<()>` from `main` goes through the standard
+/// library's `Termination` impl for `Result`, which prints `Error: ` followed
+/// by the error's `Debug` representation and always exits with code 1. That
+/// output is controlled by the standard library rather than by anyhow; for
+/// example it ends with an extra blank line, as the `Debug` representation is
+/// already terminated by a newline.
+///
+/// Returning a `Report` instead prints the error as rendered by the `Display`
+/// impl of `Report`: the error, its chain of causes, any notes and URL, and
+/// the backtrace if one was captured. The process then exits with
+/// [`Error::exit_code`][Error::exit_code], which can be chosen using
+/// [`Error::with_exit_code`][Error::with_exit_code].
+///
+/// Only the low 8 bits of the exit code are reported, as that is all most
+/// platforms preserve. An exit code whose low 8 bits are all zero, such as 0
+/// or 256, would report success, so the process exits with
+/// [`ExitCode::FAILURE`] instead.
+///
+/// # Example
+///
+/// ```
+/// use anyhow::{anyhow, Report, Result};
+///
+/// const EX_USAGE: i32 = 64;
+///
+/// fn main() -> Report {
+///     run().into()
+/// }
+///
+/// fn run() -> Result<()> {
+///     # return Ok(());
+///     let path = match std::env::args_os().nth(1) {
+///         Some(path) => path,
+///         None => return Err(anyhow!("missing input path").with_exit_code(EX_USAGE)),
+///     };
+///     # const IGNORE: &str = stringify! {
+///     ...
+///     # };
+///     # let _ = path;
+///     Ok(())
+/// }
+/// ```
+pub struct Report {
+    result: Result<(), Error>,
+}
+
+impl From<Result<(), Error>> for Report {
+    fn from(result: Result<(), Error>) -> Self {
+        Report { result }
+    }
+}
+
+impl From<Error> for Report {
+    fn from(error: Error) -> Self {
+        Report { result: Err(error) }
+    }
+}
+
+impl Debug for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Debug::fmt(&self.result, f)
+    }
+}
+
+/// Renders the text that is printed to stderr on termination, which is empty
+/// if there is no error.
+impl Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.result {
+            Ok(()) => Ok(()),
+            Err(error) => write!(f, "Error: {:?}", error),
+        }
+    }
+}
+
+impl Termination for Report {
+    fn report(self) -> ExitCode {
+        match self.result {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(ref error) => {
+                eprint!("{}", self);
+                match error.exit_code() as u8 {
+                    0 => ExitCode::FAILURE,
+                    code => ExitCode::from(code),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::anyhow;
+
+    #[test]
+    fn report_success() {
+        let report = Report::from(Ok(()));
+        assert_eq!(report.report(), ExitCode::SUCCESS);
+    }
+
+    #[test]
+    fn report_format() {
+        let report = Report::from(Ok(()));
+        assert_eq!(report.to_string(), "");
+
+        let report = Report::from(anyhow!("oh no!").context("failed to start"));
+        let expected = "\
+Error: failed to start
+
+Caused by:
+    oh no!
+";
+        assert!(report.to_string().starts_with(expected));
+    }
+
+    #[test]
+    fn report_exit_code() {
+        let report = Report::from(anyhow!("oh no!"));
+        assert_eq!(report.report(), ExitCode::FAILURE);
+
+        let report = Report::from(anyhow!("oh no!").with_exit_code(64));
+        assert_eq!(report.report(), ExitCode::from(64));
+    }
+
+    #[test]
+    fn report_exit_code_never_success() {
+        for code in [0, 256, 512] {
+            let report = Report::from(anyhow!("oh no!").with_exit_code(code));
+            assert_eq!(report.report(), ExitCode::FAILURE);
+        }
+
+        let report = Report::from(anyhow!("oh no!").with_exit_code(257));
+        assert_eq!(report.report(), ExitCode::from(1));
+    }
+}
@@ -0,0 +1,87 @@
+use crate::Error;
+use core::error::Error as StdError;
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+#[cfg(backtrace)]
+use std::backtrace::BacktraceStatus;
+
+/// Serializes as a struct with the following fields:
+///
+/// - `message`: the `Display` representation of this error;
+/// - `causes`: the `Display` representation of every lower level cause, in the
+///   order of [`chain()`][Error::chain];
+/// - `backtrace`: the backtrace as a string, present only if one was captured.
+///
+/// This is intended for logging and reporting. Errors cannot be reconstructed
+/// from their serialized form, so `Error` does not implement `Deserialize`.
+///
+/// Requires the `serde` feature.
+impl Serialize for Error {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("Error", 3)?;
+        state.serialize_field("message", &Displayed(&**self))?;
+        state.serialize_field("causes", &Causes(self))?;
+        #[cfg(backtrace)]
+        {
+            let backtrace = self.backtrace();
+            if let BacktraceStatus::Captured = backtrace.status() {
+                state.serialize_field("backtrace", &backtrace.to_string())?;
+            } else {
+                state.skip_field("backtrace")?;
+            }
+        }
+        #[cfg(not(backtrace))]
+        state.skip_field("backtrace")?;
+        state.end()
+    }
+}
+
+struct Displayed<'a>(&'a (dyn StdError + 'static));
+
+impl<'a> Serialize for Displayed<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self.0)
+    }
+}
+
+struct Causes<'a>(&'a Error);
+
+impl<'a> Serialize for Causes<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq(self.0.chain().skip(1).map(Displayed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::anyhow;
+    use serde_json::json;
+
+    #[test]
+    fn serialize_chain() {
+        let error = anyhow!("oh no!")
+            .context("failed to read config")
+            .context("failed to start");
+        let value = serde_json::to_value(&error).unwrap();
+        assert_eq!(value["message"], "failed to start");
+        assert_eq!(value["causes"].as_array().unwrap().len(), 2);
+        assert_eq!(value["causes"], json!(["failed to read config", "oh no!"]));
+    }
+
+    #[test]
+    fn serialize_without_causes() {
+        let error = anyhow!("oh no!");
+        let value = serde_json::to_value(&error).unwrap();
+        assert_eq!(value["message"], "oh no!");
+        assert_eq!(value["causes"], json!([]));
+    }
+}
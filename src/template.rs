@@ -0,0 +1,81 @@
+use crate::Error;
+
+#[cfg(backtrace)]
+use std::backtrace::Backtrace;
+
+/// Factory for errors that all carry the same message.
+///
+/// Created by [`Error::template`]. `Error` does not implement `Clone`, as the
+/// error it wraps generally cannot be cloned and its backtrace records where
+/// one particular error came from. A template instead makes a fresh `Error`
+/// each time [`make`][ErrorTemplate::make] is called, for sentinel errors
+/// that are reported from many places and are told apart by their message
+/// alone.
+///
+/// Errors made from a template do not capture a backtrace, which keeps them
+/// as cheap as possible; `Error::backtrace` returns a disabled one. They
+/// can be downcast to `&'static str`, the type of the message.
+///
+/// # Example
+///
+/// ```
+/// use anyhow::{Error, ErrorTemplate, Result};
+///
+/// static SHUTDOWN_REQUESTED: ErrorTemplate = Error::template("shutdown requested");
+///
+/// fn next_job(shutting_down: bool) -> Result<u32> {
+///     if shutting_down {
+///         return Err(SHUTDOWN_REQUESTED.make());
+///     }
+///     Ok(1)
+/// }
+///
+/// let error = next_job(true).unwrap_err();
+/// assert_eq!(error.to_string(), "shutdown requested");
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct ErrorTemplate {
+    message: &'static str,
+}
+
+impl ErrorTemplate {
+    pub(crate) const fn new(message: &'static str) -> Self {
+        ErrorTemplate { message }
+    }
+
+    /// The message of the errors made from this template.
+    pub fn message(&self) -> &'static str {
+        self.message
+    }
+
+    /// Make a new error with the template's message.
+    pub fn make(&self) -> Error {
+        #[cfg(backtrace)]
+        let backtrace = Some(Backtrace::disabled());
+
+        #[cfg(not(backtrace))]
+        let backtrace = None;
+
+        Error::new_adhoc(self.message, backtrace)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Error, ErrorTemplate};
+
+    static SHUTDOWN_REQUESTED: ErrorTemplate = Error::template("shutdown requested");
+
+    #[test]
+    fn make_twice() {
+        let first = SHUTDOWN_REQUESTED.make();
+        let second = SHUTDOWN_REQUESTED.make();
+        assert_eq!(first.to_string(), second.to_string());
+        assert_eq!(first.to_string(), "shutdown requested");
+        assert_eq!(
+            *second.downcast_ref::<&str>().unwrap(),
+            "shutdown requested"
+        );
+        assert!(!first.has_backtrace());
+    }
+}
@@ -0,0 +1,29 @@
+#![cfg(feature = "std")]
+
+use anyhow::{anyhow, Report};
+
+// The hook is global to the process, so this test runs in a binary of its own
+// rather than alongside the unit tests that format errors.
+#[test]
+fn test_hook() {
+    anyhow::set_hook(Box::new(|error, f| {
+        write!(f, "<custom report: {}>", error)?;
+        // Formatting other errors calls the hook again from within it.
+        for related in error.related() {
+            write!(f, " {:?}", related)?;
+        }
+        Ok(())
+    }));
+
+    let error = anyhow!("oh no!").context("failed");
+    assert_eq!(format!("{:?}", error), "<custom report: failed>");
+
+    let report = Report::from(anyhow!("oh no!"));
+    assert_eq!(report.to_string(), "Error: <custom report: oh no!>");
+
+    let error = anyhow!("failed to save").add_related(anyhow!("failed to clean up"));
+    assert_eq!(
+        format!("{:?}", error),
+        "<custom report: failed to save> <custom report: failed to clean up>",
+    );
+}